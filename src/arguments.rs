@@ -2,6 +2,8 @@ use std::env::ArgsOs;
 use std::ffi::OsString;
 use std::path::PathBuf;
 
+use chrono::FixedOffset;
+
 macro_rules! mark_used {
 	($used:tt) => {};
 }
@@ -13,6 +15,107 @@ macro_rules! arg_parse_error {
 	}};
 }
 
+#[derive(Debug, Clone)]
+pub struct FeedFormats {
+	pub rss: bool,
+	pub atom: bool,
+	pub json: bool,
+}
+
+impl Default for FeedFormats {
+	fn default() -> FeedFormats {
+		FeedFormats {
+			rss: true,
+			atom: false,
+			json: false,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarkdownExtensions {
+	pub tables: bool,
+	pub footnotes: bool,
+	pub strikethrough: bool,
+	pub tasklists: bool,
+	pub smart_punctuation: bool,
+	pub heading_attributes: bool,
+	pub math: bool,
+}
+
+impl Default for MarkdownExtensions {
+	fn default() -> MarkdownExtensions {
+		MarkdownExtensions {
+			tables: true,
+			..MarkdownExtensions::all(false)
+		}
+	}
+}
+
+impl MarkdownExtensions {
+	fn all(enabled: bool) -> MarkdownExtensions {
+		MarkdownExtensions {
+			tables: enabled,
+			footnotes: enabled,
+			strikethrough: enabled,
+			tasklists: enabled,
+			smart_punctuation: enabled,
+			heading_attributes: enabled,
+			math: enabled,
+		}
+	}
+
+	/*
+	 * Applies a list of extension names. A list of plain names ("footnotes")
+	 * replaces the current set entirely while a list where every name is
+	 * prefixed with `+` or `-` only turns those extensions on or off.
+	 */
+	pub fn apply(&self, items: &[&str]) -> Result<MarkdownExtensions, String> {
+		let items: Vec<&str> = items
+			.iter()
+			.map(|item| item.trim())
+			.filter(|item| !item.is_empty())
+			.collect();
+
+		let relative = items
+			.iter()
+			.all(|item| item.starts_with('+') || item.starts_with('-'));
+		let mut extensions = if relative {
+			*self
+		} else {
+			MarkdownExtensions::all(false)
+		};
+
+		for item in items {
+			let (enabled, name) = match item.strip_prefix('-') {
+				Some(name) => (false, name),
+				None => (true, item.trim_start_matches('+')),
+			};
+
+			match name {
+				"all" => extensions = MarkdownExtensions::all(enabled),
+				"none" => extensions = MarkdownExtensions::all(!enabled),
+				"tables" => extensions.tables = enabled,
+				"footnotes" => extensions.footnotes = enabled,
+				"strikethrough" => extensions.strikethrough = enabled,
+				"tasklists" => extensions.tasklists = enabled,
+				"smart-punctuation" => extensions.smart_punctuation = enabled,
+				"heading-attributes" => extensions.heading_attributes = enabled,
+				"math" => extensions.math = enabled,
+				name => return Err(format!("unknown markdown extension '{}'", name)),
+			}
+		}
+
+		Ok(extensions)
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum HighlightMode {
+	Inline,
+	Classes,
+}
+
 fn get_next_arg(args: &mut ArgsOs) -> OsString {
 	if let Some(arg) = args.next() {
 		arg
@@ -21,6 +124,17 @@ fn get_next_arg(args: &mut ArgsOs) -> OsString {
 	}
 }
 
+//Activities run as soon as they are seen so they look up any flag they depend on themselves
+fn find_flag_value(short_flag: &str, long_flag: &str) -> Option<OsString> {
+	let mut args = std::env::args_os().skip(1);
+	while let Some(arg) = args.next() {
+		if arg == short_flag || arg == long_flag {
+			return args.next();
+		}
+	}
+	None
+}
+
 macro_rules! define_flags {
 	(
 		$app_description:literal
@@ -218,6 +332,45 @@ define_flags! {
 		}
 	},
 
+	activity export_highlight_css ("-hx", "--export-highlight-css") "Write the CSS for --highlight-theme to highlight.css in the given fragments directory" {
+		witharg(dir) {
+			let theme = find_flag_value("-ht", "--highlight-theme");
+			let theme = theme.as_ref().map(|theme| theme.to_string_lossy());
+			crate::highlight::export_css(
+				theme.as_deref().unwrap_or(crate::highlight::DEFAULT_THEME),
+				&PathBuf::from(dir),
+			);
+			std::process::exit(0);
+		}
+	},
+
+	optional site_title ("-t", "--title") "Site title for the index page and RSS feed" -> String {
+		witharg(title) {
+			title.to_string_lossy().into()
+		}
+	},
+
+	optional site_description ("-ds", "--description") "Site description for the index page and RSS feed" -> String {
+		witharg(description) {
+			description.to_string_lossy().into()
+		}
+	},
+
+	optional utc_offset ("-uo", "--utc-offset") "Fixed UTC offset such as '+02:00' for post dates which lack one and for displayed dates, it does not follow daylight saving time, defaults to UTC" -> FixedOffset {
+		witharg(offset) {
+			match crate::dates::parse_offset(&offset.to_string_lossy()) {
+				Some(offset) => offset,
+				None => arg_parse_error!("Invalid UTC offset '{}'", offset.to_string_lossy()),
+			}
+		}
+	},
+
+	optional date_format ("-df", "--date-format") "strftime style format for displayed dates, %o is the ordinal day, defaults to '%A the %o of %B %Y' or the equivalent for --language" -> String {
+		witharg(format) {
+			format.to_string_lossy().into()
+		}
+	},
+
 	optional favicon ("-s", "--favicon") "Favicon image for generated pages" -> String {
 		witharg(favicon) {
 			favicon.to_string_lossy().into()
@@ -248,6 +401,85 @@ define_flags! {
 		}
 	},
 
+	optional feed_formats ("-ff", "--feeds") "Comma separated feed formats to generate (rss, atom, json), defaults to rss" -> FeedFormats {
+		witharg(formats) {
+			let mut feed_formats = FeedFormats {
+				rss: false,
+				atom: false,
+				json: false,
+			};
+
+			for format in formats.to_string_lossy().split(',') {
+				match format.trim() {
+					"rss" => feed_formats.rss = true,
+					"atom" => feed_formats.atom = true,
+					"json" => feed_formats.json = true,
+					format => arg_parse_error!("Unknown feed format '{}'", format),
+				}
+			}
+
+			feed_formats
+		}
+	},
+
+	optional markdown_extensions ("-me", "--markdown-extensions") "Comma separated markdown extensions (tables, footnotes, strikethrough, tasklists, smart-punctuation, heading-attributes, math, all), defaults to tables" -> MarkdownExtensions {
+		witharg(extensions) {
+			let extensions = extensions.to_string_lossy();
+			let items: Vec<&str> = extensions.split(',').collect();
+
+			match MarkdownExtensions::default().apply(&items) {
+				Ok(extensions) => extensions,
+				Err(err) => arg_parse_error!("{}", err),
+			}
+		}
+	},
+
+	optional sidenotes ("-sn", "--sidenotes") "Render footnotes as sidenotes in the margin next to their reference, turns on the footnotes extension" -> bool {
+		withoutarg() {
+			true
+		}
+	},
+
+	optional feed_content ("-fc", "--feed-content") "Embed the full post content in feeds" -> bool {
+		withoutarg() {
+			true
+		}
+	},
+
+	optional drafts ("-dr", "--drafts") "Include draft posts in the output with a visible draft marker" -> bool {
+		withoutarg() {
+			true
+		}
+	},
+
+	optional highlight ("-hi", "--highlight") "Highlight fenced code blocks with 'inline' styles or 'classes' styled by an exported stylesheet" -> HighlightMode {
+		witharg(mode) {
+			match mode.to_string_lossy().trim() {
+				"inline" => HighlightMode::Inline,
+				"classes" => HighlightMode::Classes,
+				mode => arg_parse_error!("Unknown highlight mode '{}'", mode),
+			}
+		}
+	},
+
+	optional highlight_theme ("-ht", "--highlight-theme") "Syntax highlighting theme, defaults to 'InspiredGitHub'" -> String {
+		witharg(theme) {
+			theme.to_string_lossy().into()
+		}
+	},
+
+	optional heading_links ("-hl", "--heading-links") "Add a self-link to every heading next to its anchor" -> bool {
+		withoutarg() {
+			true
+		}
+	},
+
+	optional future ("-fu", "--future") "Include posts dated in the future instead of holding them back" -> bool {
+		withoutarg() {
+			true
+		}
+	},
+
 	required blog_base_url ("-u", "--base-url") "Base URL for blog subfolder" -> String {
 		witharg(url) {
 			url.to_string_lossy().into()
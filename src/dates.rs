@@ -0,0 +1,552 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc};
+
+//Formats which carry their own UTC offset
+const ZONED_FORMATS: &[&str] = &[
+	"%d %b %Y %H:%M:%S %z",
+	"%Y-%m-%d %H:%M:%S %z",
+	"%Y-%m-%d %H:%M %z",
+];
+
+//Formats which are interpreted in the default UTC offset
+const LOCAL_FORMATS: &[&str] = &[
+	"%Y-%m-%dT%H:%M:%S",
+	"%Y-%m-%d %H:%M:%S",
+	"%Y-%m-%dT%H:%M",
+	"%Y-%m-%d %H:%M",
+	"%d %b %Y %H:%M:%S",
+	"%d %b %Y %H:%M",
+];
+
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%d %b %Y"];
+
+pub const ACCEPTED_FORMATS: &str = concat!(
+	"RFC 3339 / ISO 8601 ('2024-05-01T10:30:00+02:00'), ",
+	"RFC 2822 ('Wed, 01 May 2024 10:30:00 +0200'), ",
+	"'01 May 2024 10:30:00 +0200', ",
+	"date and time without an offset ('2024-05-01 10:30' or '01 May 2024 10:30:00') ",
+	"or a plain date ('2024-05-01' or '01 May 2024'), ",
+	"values without an offset use the --utc-offset default"
+);
+
+/*
+ * Parses a fixed UTC offset such as `+02:00`, `-0530`, `Z` or `UTC`.
+ */
+pub fn parse_offset(text: &str) -> Option<FixedOffset> {
+	let text = text.trim();
+	if text.eq_ignore_ascii_case("utc") || text.eq_ignore_ascii_case("z") {
+		return Some(Utc.fix());
+	}
+
+	let sign = match text.chars().next()? {
+		'+' => 1,
+		'-' => -1,
+		_ => return None,
+	};
+
+	let digits: String = text[1..].chars().filter(|c| *c != ':').collect();
+	if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+		return None;
+	}
+
+	let hours: i32 = digits[..2].parse().ok()?;
+	let minutes: i32 = digits[2..].parse().ok()?;
+	if hours > 23 || minutes > 59 {
+		return None;
+	}
+
+	FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+pub fn parse_date(text: &str, default_offset: FixedOffset) -> Option<DateTime<Utc>> {
+	let text = text.trim();
+
+	if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+		return Some(date.into());
+	}
+	if let Ok(date) = DateTime::parse_from_rfc2822(text) {
+		return Some(date.into());
+	}
+
+	for format in ZONED_FORMATS {
+		if let Ok(date) = DateTime::parse_from_str(text, format) {
+			return Some(date.into());
+		}
+	}
+
+	for format in LOCAL_FORMATS {
+		if let Ok(date) = NaiveDateTime::parse_from_str(text, format) {
+			return default_offset
+				.from_local_datetime(&date)
+				.single()
+				.map(|date| date.into());
+		}
+	}
+
+	for format in DATE_FORMATS {
+		if let Ok(date) = NaiveDate::parse_from_str(text, format) {
+			return default_offset
+				.from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+				.single()
+				.map(|date| date.into());
+		}
+	}
+
+	None
+}
+
+struct DateNames {
+	days: [&'static str; 7],
+	short_days: [&'static str; 7],
+	months: [&'static str; 12],
+	short_months: [&'static str; 12],
+}
+
+const ENGLISH: DateNames = DateNames {
+	days: [
+		"Monday",
+		"Tuesday",
+		"Wednesday",
+		"Thursday",
+		"Friday",
+		"Saturday",
+		"Sunday",
+	],
+	short_days: ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+	months: [
+		"January",
+		"February",
+		"March",
+		"April",
+		"May",
+		"June",
+		"July",
+		"August",
+		"September",
+		"October",
+		"November",
+		"December",
+	],
+	short_months: [
+		"Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+	],
+};
+
+const GERMAN: DateNames = DateNames {
+	days: [
+		"Montag",
+		"Dienstag",
+		"Mittwoch",
+		"Donnerstag",
+		"Freitag",
+		"Samstag",
+		"Sonntag",
+	],
+	short_days: ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"],
+	months: [
+		"Januar",
+		"Februar",
+		"März",
+		"April",
+		"Mai",
+		"Juni",
+		"Juli",
+		"August",
+		"September",
+		"Oktober",
+		"November",
+		"Dezember",
+	],
+	short_months: [
+		"Jan", "Feb", "Mär", "Apr", "Mai", "Jun", "Jul", "Aug", "Sep", "Okt", "Nov", "Dez",
+	],
+};
+
+const FRENCH: DateNames = DateNames {
+	days: [
+		"lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche",
+	],
+	short_days: ["lun", "mar", "mer", "jeu", "ven", "sam", "dim"],
+	months: [
+		"janvier",
+		"février",
+		"mars",
+		"avril",
+		"mai",
+		"juin",
+		"juillet",
+		"août",
+		"septembre",
+		"octobre",
+		"novembre",
+		"décembre",
+	],
+	short_months: [
+		"janv", "févr", "mars", "avr", "mai", "juin", "juil", "août", "sept", "oct", "nov", "déc",
+	],
+};
+
+const SPANISH: DateNames = DateNames {
+	days: [
+		"lunes",
+		"martes",
+		"miércoles",
+		"jueves",
+		"viernes",
+		"sábado",
+		"domingo",
+	],
+	short_days: ["lun", "mar", "mié", "jue", "vie", "sáb", "dom"],
+	months: [
+		"enero",
+		"febrero",
+		"marzo",
+		"abril",
+		"mayo",
+		"junio",
+		"julio",
+		"agosto",
+		"septiembre",
+		"octubre",
+		"noviembre",
+		"diciembre",
+	],
+	short_months: [
+		"ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sept", "oct", "nov", "dic",
+	],
+};
+
+const ITALIAN: DateNames = DateNames {
+	days: [
+		"lunedì",
+		"martedì",
+		"mercoledì",
+		"giovedì",
+		"venerdì",
+		"sabato",
+		"domenica",
+	],
+	short_days: ["lun", "mar", "mer", "gio", "ven", "sab", "dom"],
+	months: [
+		"gennaio",
+		"febbraio",
+		"marzo",
+		"aprile",
+		"maggio",
+		"giugno",
+		"luglio",
+		"agosto",
+		"settembre",
+		"ottobre",
+		"novembre",
+		"dicembre",
+	],
+	short_months: [
+		"gen", "feb", "mar", "apr", "mag", "giu", "lug", "ago", "set", "ott", "nov", "dic",
+	],
+};
+
+const DUTCH: DateNames = DateNames {
+	days: [
+		"maandag",
+		"dinsdag",
+		"woensdag",
+		"donderdag",
+		"vrijdag",
+		"zaterdag",
+		"zondag",
+	],
+	short_days: ["ma", "di", "wo", "do", "vr", "za", "zo"],
+	months: [
+		"januari",
+		"februari",
+		"maart",
+		"april",
+		"mei",
+		"juni",
+		"juli",
+		"augustus",
+		"september",
+		"oktober",
+		"november",
+		"december",
+	],
+	short_months: [
+		"jan", "feb", "mrt", "apr", "mei", "jun", "jul", "aug", "sep", "okt", "nov", "dec",
+	],
+};
+
+const PORTUGUESE: DateNames = DateNames {
+	days: [
+		"segunda-feira",
+		"terça-feira",
+		"quarta-feira",
+		"quinta-feira",
+		"sexta-feira",
+		"sábado",
+		"domingo",
+	],
+	short_days: ["seg", "ter", "qua", "qui", "sex", "sáb", "dom"],
+	months: [
+		"janeiro",
+		"fevereiro",
+		"março",
+		"abril",
+		"maio",
+		"junho",
+		"julho",
+		"agosto",
+		"setembro",
+		"outubro",
+		"novembro",
+		"dezembro",
+	],
+	short_months: [
+		"jan", "fev", "mar", "abr", "mai", "jun", "jul", "ago", "set", "out", "nov", "dez",
+	],
+};
+
+//Only the primary subtag matters, `de-AT` and `de_DE` both use German names
+fn primary_language(language: &str) -> String {
+	language
+		.split(['-', '_'])
+		.next()
+		.unwrap_or("")
+		.to_lowercase()
+}
+
+fn date_names(language: &str) -> &'static DateNames {
+	match primary_language(language).as_str() {
+		"de" => &GERMAN,
+		"fr" => &FRENCH,
+		"es" => &SPANISH,
+		"it" => &ITALIAN,
+		"nl" => &DUTCH,
+		"pt" => &PORTUGUESE,
+		_ => &ENGLISH,
+	}
+}
+
+//Used when no --date-format is given so the connecting words match the names
+pub fn default_format(language: &str) -> &'static str {
+	match primary_language(language).as_str() {
+		"de" => "%A, %o %B %Y",
+		"fr" | "nl" => "%A %o %B %Y",
+		"es" | "pt" => "%A, %o de %B de %Y",
+		"it" => "%A %o %B %Y",
+		_ => "%A the %o of %B %Y",
+	}
+}
+
+fn ordinal_day(day: u32, language: &str) -> String {
+	match primary_language(language).as_str() {
+		"de" => format!("{}.", day),
+		"fr" if day == 1 => "1er".to_string(),
+		"fr" | "es" | "it" | "nl" | "pt" => day.to_string(),
+
+		//English like the names for any language without its own
+		_ => {
+			let suffix = match (day % 10, day % 100) {
+				(_, 11..=13) => "th",
+				(1, _) => "st",
+				(2, _) => "nd",
+				(3, _) => "rd",
+				_ => "th",
+			};
+			format!("{}{}", day, suffix)
+		}
+	}
+}
+
+/*
+ * Formats a date using chrono's strftime syntax with day and month names
+ * (`%A`, `%a`, `%B`, `%b`) localized for `language` plus an extra `%o`
+ * specifier for the day of the month as an ordinal ("1st", "2nd", "22nd").
+ */
+pub fn format_date(date: &DateTime<FixedOffset>, format: &str, language: &str) -> String {
+	let names = date_names(language);
+	let weekday = date.weekday().num_days_from_monday() as usize;
+	let month = date.month0() as usize;
+
+	let mut expanded = String::with_capacity(format.len());
+	let mut characters = format.chars();
+	while let Some(character) = characters.next() {
+		if character != '%' {
+			expanded.push(character);
+			continue;
+		}
+
+		let replacement = match characters.next() {
+			Some('A') => names.days[weekday].to_string(),
+			Some('a') => names.short_days[weekday].to_string(),
+			Some('B') => names.months[month].to_string(),
+			Some('b') => names.short_months[month].to_string(),
+			Some('o') => ordinal_day(date.day(), language),
+
+			Some(other) => {
+				expanded.push('%');
+				expanded.push(other);
+				continue;
+			}
+
+			None => {
+				expanded.push_str("%%");
+				continue;
+			}
+		};
+
+		//Names are substituted before chrono sees the format so escape any percent signs
+		expanded.push_str(&replacement.replace('%', "%%"));
+	}
+
+	let mut output = String::new();
+	if write!(output, "{}", date.format(&expanded)).is_err() {
+		eprintln!("Error invalid date format '{}'", format);
+		std::process::exit(-1);
+	}
+	output
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn date(text: &str) -> DateTime<FixedOffset> {
+		DateTime::parse_from_rfc3339(text).unwrap()
+	}
+
+	fn utc(text: &str) -> DateTime<Utc> {
+		date(text).into()
+	}
+
+	fn offset(text: &str) -> FixedOffset {
+		parse_offset(text).unwrap()
+	}
+
+	#[test]
+	fn offsets() {
+		assert_eq!(offset("UTC"), Utc.fix());
+		assert_eq!(offset("z"), Utc.fix());
+		assert_eq!(offset("+02:00").local_minus_utc(), 2 * 3600);
+		assert_eq!(offset("-0530").local_minus_utc(), -(5 * 3600 + 30 * 60));
+		assert_eq!(parse_offset("02:00"), None);
+		assert_eq!(parse_offset("+24:00"), None);
+		assert_eq!(parse_offset("Europe/Berlin"), None);
+	}
+
+	#[test]
+	fn dates_with_an_offset() {
+		let expected = utc("2024-05-01T08:30:00+00:00");
+		let default = offset("-05:00");
+		for text in [
+			"2024-05-01T10:30:00+02:00",
+			"Wed, 01 May 2024 10:30:00 +0200",
+			"01 May 2024 10:30:00 +0200",
+			"2024-05-01 10:30:00 +0200",
+			"2024-05-01 10:30 +0200",
+		] {
+			assert_eq!(parse_date(text, default), Some(expected), "{}", text);
+		}
+	}
+
+	#[test]
+	fn dates_without_an_offset_use_the_default() {
+		let expected = utc("2024-05-01T08:30:00+00:00");
+		let default = offset("+02:00");
+		for text in [
+			"2024-05-01T10:30:00",
+			"2024-05-01 10:30:00",
+			"2024-05-01T10:30",
+			" 2024-05-01 10:30 ",
+			"01 May 2024 10:30:00",
+			"01 May 2024 10:30",
+		] {
+			assert_eq!(parse_date(text, default), Some(expected), "{}", text);
+		}
+	}
+
+	#[test]
+	fn plain_dates_start_at_midnight() {
+		let expected = utc("2024-04-30T22:00:00+00:00");
+		assert_eq!(parse_date("2024-05-01", offset("+02:00")), Some(expected));
+		assert_eq!(parse_date("01 May 2024", offset("+02:00")), Some(expected));
+	}
+
+	#[test]
+	fn invalid_dates() {
+		assert_eq!(parse_date("yesterday", Utc.fix()), None);
+		assert_eq!(parse_date("2024-13-01", Utc.fix()), None);
+		assert_eq!(parse_date("", Utc.fix()), None);
+	}
+
+	#[test]
+	fn english_ordinals() {
+		let expected = [
+			(1, "1st"),
+			(2, "2nd"),
+			(3, "3rd"),
+			(4, "4th"),
+			(11, "11th"),
+			(12, "12th"),
+			(13, "13th"),
+			(21, "21st"),
+			(22, "22nd"),
+			(23, "23rd"),
+			(31, "31st"),
+		];
+		for (day, ordinal) in expected {
+			assert_eq!(ordinal_day(day, "en"), ordinal);
+			assert_eq!(ordinal_day(day, "en-GB"), ordinal);
+		}
+	}
+
+	#[test]
+	fn localized_ordinals() {
+		assert_eq!(ordinal_day(1, "de"), "1.");
+		assert_eq!(ordinal_day(21, "de_AT"), "21.");
+		assert_eq!(ordinal_day(1, "fr"), "1er");
+		assert_eq!(ordinal_day(2, "fr"), "2");
+		assert_eq!(ordinal_day(3, "es"), "3");
+	}
+
+	#[test]
+	fn unknown_languages_fall_back_to_english() {
+		assert_eq!(ordinal_day(1, "ja"), "1st");
+		assert_eq!(ordinal_day(22, "sv"), "22nd");
+		assert_eq!(default_format("sv"), default_format("en"));
+
+		let date = date("2024-05-01T10:00:00+00:00");
+		assert_eq!(
+			format_date(&date, default_format("ja"), "ja"),
+			"Wednesday the 1st of May 2024"
+		);
+	}
+
+	#[test]
+	fn localized_names() {
+		let date = date("2024-05-01T10:00:00+00:00");
+		assert_eq!(
+			format_date(&date, default_format("de"), "de"),
+			"Mittwoch, 1. Mai 2024"
+		);
+		assert_eq!(
+			format_date(&date, default_format("fr"), "fr"),
+			"mercredi 1er mai 2024"
+		);
+		assert_eq!(
+			format_date(&date, "%a %d %b %Y %H:%M", "es"),
+			"mié 01 may 2024 10:00"
+		);
+		assert_eq!(
+			format_date(&date, "%A %o %B", "pt-BR"),
+			"quarta-feira 1 maio"
+		);
+	}
+
+	#[test]
+	fn percent_signs_survive_substitution() {
+		let date = date("2024-03-02T00:00:00+00:00");
+		assert_eq!(format_date(&date, "100%% %B", "en"), "100% March");
+	}
+}
@@ -0,0 +1,651 @@
+/*
+ * Parses a `---` YAML or `+++` TOML front matter block at the very start of a
+ * markdown file. Only the flat subset which makes sense for post metadata is
+ * understood: scalar values (plain, quoted and multi-line) and lists. Nested
+ * YAML mappings are rejected while TOML tables are flattened into dotted keys.
+ */
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataValue {
+	Text(String),
+	List(Vec<String>),
+}
+
+#[derive(Debug)]
+pub struct FrontMatter {
+	pub fields: Vec<(String, MetadataValue)>,
+	//Byte length of the block including both delimiter lines
+	pub length: usize,
+	//Problems which did not stop parsing but likely surprise the author
+	pub warnings: Vec<String>,
+}
+
+enum Syntax {
+	Yaml,
+	Toml,
+}
+
+pub fn parse(input: &str) -> Result<Option<FrontMatter>, String> {
+	let input_start = input.len() - input.trim_start_matches('\u{feff}').len();
+	let input = &input[input_start..];
+
+	let first_line_end = input
+		.find('\n')
+		.map(|index| index + 1)
+		.unwrap_or(input.len());
+	let syntax = match input[..first_line_end].trim_end() {
+		"---" => Syntax::Yaml,
+		"+++" => Syntax::Toml,
+		_ => return Ok(None),
+	};
+
+	let mut lines = Vec::new();
+	let mut offset = first_line_end;
+	let mut length = None;
+	for line in input[first_line_end..].split_inclusive('\n') {
+		offset += line.len();
+
+		let line = line.trim_end_matches('\n').trim_end_matches('\r');
+		let is_delimiter = match syntax {
+			Syntax::Yaml => line == "---" || line == "...",
+			Syntax::Toml => line == "+++",
+		};
+		if is_delimiter {
+			length = Some(offset);
+			break;
+		}
+
+		lines.push(line);
+	}
+
+	/*
+	 * A leading `---` is also a thematic break, so the block only counts as
+	 * front matter when it is closed and starts with something shaped like a
+	 * key. Anything else is left for pulldown-cmark.
+	 */
+	let starts_with_field = lines
+		.iter()
+		.find(|line| !is_blank_or_comment(line))
+		.map(|line| looks_like_field(line, &syntax))
+		.unwrap_or(false);
+	let length = match length {
+		Some(length) if starts_with_field => input_start + length,
+		None if starts_with_field => return Err("front matter block is never closed".to_string()),
+		_ => return Ok(None),
+	};
+
+	let mut warnings = Vec::new();
+	let fields = match syntax {
+		Syntax::Yaml => parse_yaml(&lines, &mut warnings)?,
+		Syntax::Toml => parse_toml(&lines)?,
+	};
+
+	Ok(Some(FrontMatter {
+		fields,
+		length,
+		warnings,
+	}))
+}
+
+fn is_key_character(character: char) -> bool {
+	character.is_alphanumeric() || matches!(character, '_' | '-' | '.')
+}
+
+fn looks_like_field(line: &str, syntax: &Syntax) -> bool {
+	if indentation(line) > 0 {
+		return false;
+	}
+
+	let key = match syntax {
+		Syntax::Yaml => match line
+			.find(": ")
+			.or_else(|| line.strip_suffix(':').map(str::len))
+		{
+			Some(colon) => &line[..colon],
+			None => return false,
+		},
+		Syntax::Toml => {
+			if line.starts_with('[') {
+				return true;
+			}
+			match line.find('=') {
+				Some(equals) => line[..equals].trim_end(),
+				None => return false,
+			}
+		}
+	};
+
+	let unquoted = key
+		.strip_prefix('"')
+		.and_then(|key| key.strip_suffix('"'))
+		.or_else(|| {
+			key.strip_prefix('\'')
+				.and_then(|key| key.strip_suffix('\''))
+		});
+	match unquoted {
+		Some(key) => !key.is_empty(),
+		None => !key.is_empty() && key.chars().all(is_key_character),
+	}
+}
+
+fn indentation(line: &str) -> usize {
+	line.len() - line.trim_start().len()
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+	let trimmed = line.trim();
+	trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+fn strip_comment(value: &str) -> &str {
+	match value.find(" #") {
+		Some(index) => value[..index].trim_end(),
+		None => value,
+	}
+}
+
+fn unescape_double_quoted(value: &str) -> Result<String, String> {
+	let mut output = String::with_capacity(value.len());
+	let mut characters = value.chars();
+
+	while let Some(character) = characters.next() {
+		if character != '\\' {
+			output.push(character);
+			continue;
+		}
+
+		match characters.next() {
+			Some('n') => output.push('\n'),
+			Some('t') => output.push('\t'),
+			Some('r') => output.push('\r'),
+			Some('"') => output.push('"'),
+			Some('\\') => output.push('\\'),
+			Some('/') => output.push('/'),
+			Some(other) => return Err(format!("unsupported escape sequence '\\{}'", other)),
+			None => return Err("string ends with a lone backslash".to_string()),
+		}
+	}
+
+	Ok(output)
+}
+
+/*
+ * Parses a single quoted or unquoted scalar. Quotes follow YAML rules which
+ * are close enough to TOML basic and literal strings for our purposes.
+ */
+fn parse_scalar(value: &str) -> Result<String, String> {
+	let value = value.trim();
+
+	let (text, rest) = if let Some(inner) = value.strip_prefix('"') {
+		match closing_quote(inner, '"') {
+			Some(end) => (unescape_double_quoted(&inner[..end])?, &inner[end + 1..]),
+			None => return Err(format!("unterminated string {}", value)),
+		}
+	} else if let Some(inner) = value.strip_prefix('\'') {
+		match closing_quote(inner, '\'') {
+			Some(end) => (inner[..end].replace("''", "'"), &inner[end + 1..]),
+			None => return Err(format!("unterminated string {}", value)),
+		}
+	} else {
+		return Ok(strip_comment(value).to_string());
+	};
+
+	//Only a comment may follow the closing quote
+	let rest = rest.trim();
+	if rest.is_empty() || rest.starts_with('#') {
+		Ok(text)
+	} else {
+		Err(format!(
+			"unexpected text '{}' after quoted string {}",
+			rest, value
+		))
+	}
+}
+
+//Byte index of the quote ending a string, skipping `\"` and `''` escapes
+fn closing_quote(inner: &str, quote: char) -> Option<usize> {
+	let mut characters = inner.char_indices().peekable();
+
+	while let Some((index, character)) = characters.next() {
+		if quote == '"' && character == '\\' {
+			characters.next();
+		} else if character == quote {
+			if quote == '\'' && matches!(characters.peek(), Some((_, '\''))) {
+				characters.next();
+			} else {
+				return Some(index);
+			}
+		}
+	}
+
+	None
+}
+
+//Parses the contents of a `[a, "b", c]` list with the brackets already removed
+fn parse_inline_list(contents: &str) -> Result<Vec<String>, String> {
+	let mut items = Vec::new();
+	let mut current = String::new();
+	let mut quote = None;
+
+	for character in contents.chars() {
+		match (quote, character) {
+			(None, '"') | (None, '\'') => {
+				quote = Some(character);
+				current.push(character);
+			}
+			(Some(open), _) if open == character => {
+				quote = None;
+				current.push(character);
+			}
+			(None, ',') => {
+				items.push(std::mem::take(&mut current));
+			}
+			(None, '\n') => current.push(' '),
+			_ => current.push(character),
+		}
+	}
+	items.push(current);
+
+	let mut list = Vec::new();
+	for item in items {
+		let item = item.trim();
+		if item.is_empty() || item.starts_with('#') {
+			continue;
+		}
+		list.push(parse_scalar(item)?);
+	}
+
+	Ok(list)
+}
+
+fn parse_yaml(
+	lines: &[&str],
+	warnings: &mut Vec<String>,
+) -> Result<Vec<(String, MetadataValue)>, String> {
+	let mut fields = Vec::new();
+	let mut index = 0;
+
+	while index < lines.len() {
+		let line = lines[index];
+		index += 1;
+
+		if is_blank_or_comment(line) {
+			continue;
+		}
+		if indentation(line) > 0 {
+			return Err(format!("unexpected indented line '{}'", line.trim()));
+		}
+
+		let colon = match line
+			.find(": ")
+			.or_else(|| line.strip_suffix(':').map(|l| l.len()))
+		{
+			Some(colon) => colon,
+			None => return Err(format!("expected 'key: value' but found '{}'", line)),
+		};
+		let key = parse_scalar(&line[..colon])?;
+		let value = line[colon + 1..].trim();
+		let rest = strip_comment(value);
+
+		//Collect the indented (or blank) lines which belong to this key
+		let block_start = index;
+		while index < lines.len()
+			&& (lines[index].trim().is_empty() || indentation(lines[index]) > 0)
+		{
+			index += 1;
+		}
+		let block = &lines[block_start..index];
+		let block_indent = block
+			.iter()
+			.filter(|line| !line.trim().is_empty())
+			.map(|line| indentation(line))
+			.min()
+			.unwrap_or(0);
+
+		let value = if rest.starts_with('|') || rest.starts_with('>') {
+			let literal = rest.starts_with('|');
+			let keep_trailing = rest.ends_with('+');
+			let strip_trailing = rest.ends_with('-');
+
+			let mut text = String::new();
+			for (line_index, line) in block.iter().enumerate() {
+				let line = if line.len() >= block_indent {
+					&line[block_indent..]
+				} else {
+					""
+				};
+
+				if line_index > 0 {
+					let previous_blank = block[line_index - 1].trim().is_empty();
+					if literal || line.is_empty() {
+						text.push('\n');
+					} else if !previous_blank {
+						text.push(' ');
+					}
+				}
+				text.push_str(line);
+			}
+
+			if strip_trailing {
+				text = text.trim_end().to_string();
+			} else if !keep_trailing {
+				text = text.trim_end().to_string();
+				text.push('\n');
+			}
+
+			MetadataValue::Text(text)
+		} else if let Some(inner) = rest.strip_prefix('[') {
+			let mut contents = inner.to_string();
+			for line in block {
+				contents.push('\n');
+				contents.push_str(line.trim());
+			}
+			let contents = contents.trim_end();
+			match contents.strip_suffix(']') {
+				Some(contents) => MetadataValue::List(parse_inline_list(contents)?),
+				None => return Err(format!("unterminated list for key '{}'", key)),
+			}
+		} else if rest.is_empty() {
+			let items: Vec<&str> = block
+				.iter()
+				.filter(|line| !is_blank_or_comment(line))
+				.copied()
+				.collect();
+
+			if items.is_empty() {
+				MetadataValue::Text(String::new())
+			} else {
+				let mut list = Vec::new();
+				for item in items {
+					match item.trim().strip_prefix('-') {
+						Some(item) => list.push(parse_scalar(item)?),
+						None => {
+							return Err(format!(
+								"nested mappings are not supported (key '{}')",
+								key
+							))
+						}
+					}
+				}
+				MetadataValue::List(list)
+			}
+		} else {
+			//Plain or quoted scalar, possibly continued on following indented lines
+			let mut text = value.to_string();
+			for line in block {
+				if !line.trim().is_empty() {
+					text.push(' ');
+					text.push_str(line.trim());
+				}
+			}
+
+			let is_quoted = text.starts_with('"') || text.starts_with('\'');
+			if !is_quoted && strip_comment(&text) != text {
+				warnings.push(format!(
+					"value of '{}' is cut off at ' #' which starts a comment, quote it to keep '{}'",
+					key, text
+				));
+			}
+			MetadataValue::Text(parse_scalar(&text)?)
+		};
+
+		fields.push((key, value));
+	}
+
+	Ok(fields)
+}
+
+fn parse_toml(lines: &[&str]) -> Result<Vec<(String, MetadataValue)>, String> {
+	let mut fields = Vec::new();
+	let mut table = String::new();
+	let mut index = 0;
+
+	while index < lines.len() {
+		let line = lines[index].trim();
+		index += 1;
+
+		if is_blank_or_comment(line) {
+			continue;
+		}
+
+		if let Some(name) = line.strip_prefix('[') {
+			match strip_comment(name).strip_suffix(']') {
+				Some(name) => {
+					table = format!("{}.", parse_scalar(name)?);
+					continue;
+				}
+				None => return Err(format!("malformed table header '{}'", line)),
+			}
+		}
+
+		let equals = match line.find('=') {
+			Some(equals) => equals,
+			None => return Err(format!("expected 'key = value' but found '{}'", line)),
+		};
+		let key = format!("{}{}", table, parse_scalar(&line[..equals])?);
+		let rest = line[equals + 1..].trim();
+
+		let value = if rest.starts_with("\"\"\"") || rest.starts_with("'''") {
+			let delimiter = &rest[..3];
+			let mut text = rest[3..].to_string();
+
+			while !text.contains(delimiter) {
+				if index >= lines.len() {
+					return Err(format!("unterminated multi-line string for key '{}'", key));
+				}
+				text.push('\n');
+				text.push_str(lines[index]);
+				index += 1;
+			}
+
+			let end = text.find(delimiter).unwrap_or(text.len());
+			let text = text[..end].strip_prefix('\n').unwrap_or(&text[..end]);
+
+			if delimiter == "\"\"\"" {
+				MetadataValue::Text(unescape_double_quoted(text)?)
+			} else {
+				MetadataValue::Text(text.to_string())
+			}
+		} else if let Some(inner) = rest.strip_prefix('[') {
+			let mut contents = strip_comment(inner).to_string();
+
+			while !contents.trim_end().ends_with(']') {
+				if index >= lines.len() {
+					return Err(format!("unterminated array for key '{}'", key));
+				}
+				contents.push('\n');
+				contents.push_str(strip_comment(lines[index].trim()));
+				index += 1;
+			}
+
+			let contents = contents.trim_end();
+			MetadataValue::List(parse_inline_list(&contents[..contents.len() - 1])?)
+		} else {
+			MetadataValue::Text(parse_scalar(rest)?)
+		};
+
+		fields.push((key, value));
+	}
+
+	Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn text(value: &str) -> MetadataValue {
+		MetadataValue::Text(value.to_string())
+	}
+
+	fn list(items: &[&str]) -> MetadataValue {
+		MetadataValue::List(items.iter().map(|item| item.to_string()).collect())
+	}
+
+	fn fields(input: &str) -> Vec<(String, MetadataValue)> {
+		parse(input)
+			.expect("front matter should parse")
+			.expect("front matter should be detected")
+			.fields
+	}
+
+	fn field(input: &str, key: &str) -> MetadataValue {
+		fields(input)
+			.into_iter()
+			.find(|(name, _)| name == key)
+			.map(|(_, value)| value)
+			.expect("key should be present")
+	}
+
+	#[test]
+	fn length_covers_both_delimiters() {
+		let input = "---\ntitle: Hello\n---\nBody\n";
+		let front_matter = parse(input).unwrap().unwrap();
+		assert_eq!(&input[front_matter.length..], "Body\n");
+	}
+
+	#[test]
+	fn thematic_break_is_not_front_matter() {
+		assert!(parse("---\n\nSome text\n").unwrap().is_none());
+		assert!(parse("---\nSome text\n---\n").unwrap().is_none());
+		assert!(parse("---\n# Heading\n\nMore text\n\n---\n")
+			.unwrap()
+			.is_none());
+		assert!(parse("+++\nNot toml\n+++\n").unwrap().is_none());
+	}
+
+	#[test]
+	fn unclosed_block_is_an_error() {
+		assert!(parse("---\ntitle: Hello\n\nBody\n").is_err());
+		assert!(parse("+++\ntitle = \"Hello\"\n").is_err());
+		//Without a field it is just a thematic break at the end of the post
+		assert!(parse("---\n\nBody\n").unwrap().is_none());
+	}
+
+	#[test]
+	fn comment_in_plain_value_warns() {
+		let front_matter = parse("---\ntitle: Issue #5\nquoted: \"Issue #5\"\n---\n")
+			.unwrap()
+			.unwrap();
+		assert_eq!(
+			front_matter.fields,
+			vec![
+				("title".to_string(), text("Issue")),
+				("quoted".to_string(), text("Issue #5")),
+			]
+		);
+		assert_eq!(front_matter.warnings.len(), 1);
+		assert!(front_matter.warnings[0].contains("'title'"));
+	}
+
+	#[test]
+	fn yaml_quoting() {
+		let input = concat!(
+			"---\n",
+			"plain: Hello world # comment\n",
+			"double: \"Say \\\"hi\\\"\\n\"\n",
+			"single: 'It''s here'\n",
+			"hash: \"C # rocks\"\n",
+			"commented: \"Quoted\" # comment\n",
+			"\"quoted key\": value\n",
+			"---\n",
+		);
+		assert_eq!(
+			fields(input),
+			vec![
+				("plain".to_string(), text("Hello world")),
+				("double".to_string(), text("Say \"hi\"\n")),
+				("single".to_string(), text("It's here")),
+				("hash".to_string(), text("C # rocks")),
+				("commented".to_string(), text("Quoted")),
+				("quoted key".to_string(), text("value")),
+			]
+		);
+	}
+
+	#[test]
+	fn text_after_closing_quote_is_an_error() {
+		assert!(parse("---\ntitle: \"a\" b\n---\n").is_err());
+		assert!(parse("---\ntitle: 'a' b\n---\n").is_err());
+		assert!(parse("+++\ntitle = \"a\" b\n+++\n").is_err());
+	}
+
+	#[test]
+	fn unterminated_string_is_an_error() {
+		assert!(parse("---\ntitle: \"a\n---\n").is_err());
+	}
+
+	#[test]
+	fn yaml_lists() {
+		let input = concat!(
+			"---\n",
+			"inline: [a, \"b, c\", 'd']\n",
+			"multi_line: [a,\n",
+			"  b]\n",
+			"block:\n",
+			"  - one\n",
+			"  # comment\n",
+			"  - \"two\"\n",
+			"---\n",
+		);
+		assert_eq!(field(input, "inline"), list(&["a", "b, c", "d"]));
+		assert_eq!(field(input, "multi_line"), list(&["a", "b"]));
+		assert_eq!(field(input, "block"), list(&["one", "two"]));
+	}
+
+	#[test]
+	fn yaml_nested_mapping_is_an_error() {
+		assert!(parse("---\nauthor:\n  name: Someone\n---\n").is_err());
+	}
+
+	#[test]
+	fn yaml_block_scalars() {
+		let input = concat!(
+			"---\n",
+			"literal: |\n",
+			"  first\n",
+			"  second\n",
+			"folded: >\n",
+			"  first\n",
+			"  second\n",
+			"\n",
+			"  third\n",
+			"stripped: |-\n",
+			"  text\n",
+			"continued: plain\n",
+			"  text\n",
+			"---\n",
+		);
+		assert_eq!(field(input, "literal"), text("first\nsecond\n"));
+		assert_eq!(field(input, "folded"), text("first second\nthird\n"));
+		assert_eq!(field(input, "stripped"), text("text"));
+		assert_eq!(field(input, "continued"), text("plain text"));
+	}
+
+	#[test]
+	fn toml_values_and_tables() {
+		let input = concat!(
+			"+++\n",
+			"title = \"Hello\" # comment\n",
+			"literal = 'C:\\path'\n",
+			"tags = [\"a\",\n",
+			"  \"b\"]\n",
+			"description = \"\"\"\n",
+			"Line one\n",
+			"Line two\"\"\"\n",
+			"[extra]\n",
+			"key = \"value\"\n",
+			"+++\n",
+		);
+		assert_eq!(
+			fields(input),
+			vec![
+				("title".to_string(), text("Hello")),
+				("literal".to_string(), text("C:\\path")),
+				("tags".to_string(), list(&["a", "b"])),
+				("description".to_string(), text("Line one\nLine two")),
+				("extra.key".to_string(), text("value")),
+			]
+		);
+	}
+}
@@ -0,0 +1,147 @@
+use std::path::Path;
+use std::process::Command;
+
+use chrono::{DateTime, Utc};
+
+/*
+ * Dates are read by running the `git` binary against the repository which
+ * contains the post. Any failure (no git installed, not a repository, file
+ * never committed) is reported as `None` so callers can fall back to their
+ * usual error.
+ */
+
+fn git_log_dates(dir: &Path, extra_args: &[&str], pathspec: &Path) -> Vec<DateTime<Utc>> {
+	let output = Command::new("git")
+		.arg("-C")
+		.arg(dir)
+		.arg("log")
+		.arg("--format=%aI")
+		.args(extra_args)
+		.arg("--")
+		.arg(pathspec)
+		.output();
+
+	let output = match output {
+		Ok(output) if output.status.success() => output,
+		_ => return Vec::new(),
+	};
+
+	String::from_utf8_lossy(&output.stdout)
+		.lines()
+		.filter_map(|line| DateTime::parse_from_rfc3339(line.trim()).ok())
+		.map(|date| date.into())
+		.collect()
+}
+
+//Author date of the commit which first added the file, following renames
+pub fn first_added(file: &Path) -> Option<DateTime<Utc>> {
+	let dir = file.parent()?;
+	let file_name = file.file_name()?;
+
+	let dates = git_log_dates(dir, &["--follow", "--diff-filter=A"], Path::new(file_name));
+	dates.last().copied()
+}
+
+//Author date of the most recent commit touching anything in the directory
+pub fn last_modified(dir: &Path) -> Option<DateTime<Utc>> {
+	let dates = git_log_dates(dir, &["-1"], Path::new("."));
+	dates.first().copied()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::path::PathBuf;
+
+	struct TempRepository {
+		dir: PathBuf,
+	}
+
+	impl TempRepository {
+		fn new(name: &str) -> TempRepository {
+			let dir =
+				std::env::temp_dir().join(format!("floc_blog_{}_{}", name, std::process::id()));
+			let _ = std::fs::remove_dir_all(&dir);
+			std::fs::create_dir_all(&dir).unwrap();
+
+			let repository = TempRepository { dir };
+			repository.git(&["init", "--quiet"], "2024-01-01T00:00:00+00:00");
+			repository
+		}
+
+		fn git(&self, args: &[&str], date: &str) {
+			let status = Command::new("git")
+				.arg("-C")
+				.arg(&self.dir)
+				.args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+				.args(["-c", "commit.gpgsign=false"])
+				.args(args)
+				.env("GIT_AUTHOR_DATE", date)
+				.env("GIT_COMMITTER_DATE", date)
+				.status()
+				.expect("git should be installed to run these tests");
+			assert!(status.success(), "git {:?} failed", args);
+		}
+
+		fn commit(&self, file: &str, contents: &str, date: &str) {
+			let path = self.dir.join(file);
+			std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+			std::fs::write(path, contents).unwrap();
+
+			self.git(&["add", "--all"], date);
+			self.git(&["commit", "--quiet", "-m", file], date);
+		}
+	}
+
+	impl Drop for TempRepository {
+		fn drop(&mut self) {
+			let _ = std::fs::remove_dir_all(&self.dir);
+		}
+	}
+
+	fn date(text: &str) -> DateTime<Utc> {
+		DateTime::parse_from_rfc3339(text).unwrap().into()
+	}
+
+	#[test]
+	fn dates_from_history() {
+		let repository = TempRepository::new("history");
+		repository.commit("post/draft.md", "Draft", "2024-05-01T10:00:00+02:00");
+		repository.git(
+			&["mv", "post/draft.md", "post/content.md"],
+			"2024-05-02T10:00:00+00:00",
+		);
+		repository.git(
+			&["commit", "--quiet", "-m", "rename"],
+			"2024-05-02T10:00:00+00:00",
+		);
+		repository.commit("post/image.png", "Image", "2024-06-01T12:00:00+00:00");
+		repository.commit("other/content.md", "Other", "2024-07-01T12:00:00+00:00");
+
+		let post = repository.dir.join("post");
+		assert_eq!(
+			first_added(&post.join("content.md")),
+			Some(date("2024-05-01T08:00:00+00:00"))
+		);
+		assert_eq!(
+			last_modified(&post),
+			Some(date("2024-06-01T12:00:00+00:00"))
+		);
+	}
+
+	#[test]
+	fn untracked_files_have_no_dates() {
+		let repository = TempRepository::new("untracked");
+		repository.commit("post/content.md", "Post", "2024-05-01T10:00:00+00:00");
+		std::fs::create_dir_all(repository.dir.join("new")).unwrap();
+		std::fs::write(repository.dir.join("new/content.md"), "New").unwrap();
+
+		assert_eq!(first_added(&repository.dir.join("new/content.md")), None);
+		assert_eq!(last_modified(&repository.dir.join("new")), None);
+		assert_eq!(
+			first_added(&std::env::temp_dir().join("floc_blog_missing/content.md")),
+			None
+		);
+	}
+}
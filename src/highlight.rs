@@ -0,0 +1,148 @@
+use std::path::Path;
+
+use syntect::highlighting::{Color, Theme, ThemeSet};
+use syntect::html::{
+	append_highlighted_html_for_styled_line, css_for_theme_with_class_style, ClassStyle,
+	ClassedHTMLGenerator, IncludeBackground,
+};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use crate::arguments::HighlightMode;
+
+/*
+ * Highlights fenced code blocks at build time with syntect's bundled
+ * grammars and themes. Inline mode bakes the theme colors into `style`
+ * attributes while class mode emits `hl-` prefixed classes which are
+ * styled by a stylesheet exported with `--export-highlight-css`.
+ */
+
+pub const DEFAULT_THEME: &str = "InspiredGitHub";
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+pub struct Highlighter {
+	mode: HighlightMode,
+	syntaxes: SyntaxSet,
+	theme: Theme,
+}
+
+fn load_theme(name: &str) -> Theme {
+	let mut themes = ThemeSet::load_defaults().themes;
+
+	match themes.remove(name) {
+		Some(theme) => theme,
+
+		None => {
+			let available: Vec<&str> = themes.keys().map(String::as_str).collect();
+			eprintln!(
+				"Error unknown highlight theme '{}', available themes are: {}",
+				name,
+				available.join(", ")
+			);
+			std::process::exit(-1);
+		}
+	}
+}
+
+fn css_color(color: Color) -> String {
+	format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+impl Highlighter {
+	pub fn new(mode: HighlightMode, theme_name: &str) -> Highlighter {
+		Highlighter {
+			mode,
+			syntaxes: SyntaxSet::load_defaults_newlines(),
+			theme: load_theme(theme_name),
+		}
+	}
+
+	/*
+	 * Returns the complete `<pre>` block for the code or `None` if the
+	 * language is not recognized, in which case the block should be left
+	 * for pulldown-cmark to output as plain code.
+	 */
+	pub fn highlight(&self, info: &str, code: &str) -> Option<String> {
+		//Only the first word of an info string like "rust,ignore" names the language
+		let language = info.split([' ', ',']).next().unwrap_or("");
+		if language.is_empty() {
+			return None;
+		}
+		let syntax = self.syntaxes.find_syntax_by_token(language)?;
+
+		let mut html = String::new();
+		match self.mode {
+			HighlightMode::Inline => {
+				let background = self.theme.settings.background.unwrap_or(Color::WHITE);
+				let foreground = self.theme.settings.foreground.unwrap_or(Color::BLACK);
+				html.push_str(&format!(
+					r#"<pre class="Highlighted" style="background-color:{};color:{};"><code class="language-{}">"#,
+					css_color(background),
+					css_color(foreground),
+					crate::escape_xml(language)
+				));
+
+				let mut lines = syntect::easy::HighlightLines::new(syntax, &self.theme);
+				for line in LinesWithEndings::from(code) {
+					let regions = lines.highlight_line(line, &self.syntaxes).ok()?;
+					append_highlighted_html_for_styled_line(
+						&regions,
+						IncludeBackground::IfDifferent(background),
+						&mut html,
+					)
+					.ok()?;
+				}
+			}
+
+			HighlightMode::Classes => {
+				html.push_str(&format!(
+					r#"<pre class="Highlighted hl-code"><code class="language-{}">"#,
+					crate::escape_xml(language)
+				));
+
+				let mut generator =
+					ClassedHTMLGenerator::new_with_class_style(syntax, &self.syntaxes, CLASS_STYLE);
+				for line in LinesWithEndings::from(code) {
+					generator
+						.parse_html_for_line_which_includes_newline(line)
+						.ok()?;
+				}
+				html.push_str(&generator.finalize());
+			}
+		}
+
+		html.push_str("</code></pre>\n");
+		Some(html)
+	}
+}
+
+//Writes the stylesheet for class based output as `highlight.css` in `dir`
+pub fn export_css(theme_name: &str, dir: &Path) {
+	let theme = load_theme(theme_name);
+
+	let css = match css_for_theme_with_class_style(&theme, CLASS_STYLE) {
+		Ok(css) => css,
+
+		Err(err) => {
+			eprintln!("Error generating CSS for theme '{}': {}", theme_name, err);
+			std::process::exit(-1);
+		}
+	};
+
+	let path = dir.join("highlight.css");
+	if let Err(err) = std::fs::write(&path, css) {
+		eprintln!(
+			"Error writing highlight CSS '{}': {}",
+			path.to_string_lossy(),
+			err
+		);
+		std::process::exit(-1);
+	}
+
+	println!(
+		"Wrote highlight CSS for theme '{}' to '{}'",
+		theme_name,
+		path.to_string_lossy()
+	);
+}
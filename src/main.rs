@@ -1,21 +1,61 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::OsStr;
 use std::fmt::Write;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Offset, Utc};
 
-use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag};
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag, TagEnd};
 
 mod arguments;
+mod dates;
+mod front_matter;
+mod git;
+mod highlight;
+mod math;
 mod template;
 
-use arguments::Arguments;
-use template::format_template;
+use arguments::{Arguments, MarkdownExtensions};
+use front_matter::MetadataValue;
+use highlight::Highlighter;
+use template::{format_template, Value, Values};
 
 pub const VERSION: &str = "0.0.1";
 
+//Average adult silent reading speed for prose
+const WORDS_PER_MINUTE: usize = 200;
+
+const DEFAULT_LAYOUT_FRAGMENT: &str = include_str!("default_fragments/layout.html");
+const DEFAULT_POST_FRAGMENT: &str = include_str!("default_fragments/post.html");
+const DEFAULT_TAGS_FRAGMENT: &str = include_str!("default_fragments/tags.html");
+const DEFAULT_SIDENOTES_CSS: &str = include_str!("default_fragments/sidenotes.css");
+
+//Fenced block directives, more can be added or these replaced in `directives/`
+const DEFAULT_DIRECTIVES: &[(&str, &str)] = &[
+	(
+		"note",
+		include_str!("default_fragments/directives/note.html"),
+	),
+	(
+		"warning",
+		include_str!("default_fragments/directives/warning.html"),
+	),
+	(
+		"aside",
+		include_str!("default_fragments/directives/aside.html"),
+	),
+	(
+		"details",
+		include_str!("default_fragments/directives/details.html"),
+	),
+	(
+		"image_description",
+		include_str!("default_fragments/directives/image_description.html"),
+	),
+];
+
 macro_rules! multiline {
 	( $($line:expr)* ) => {
 		concat!( $($line, "\n"),* )
@@ -24,9 +64,9 @@ macro_rules! multiline {
 
 macro_rules! map {
 	[ $($key:expr => $value:expr,)* ] => {{
-		let mut map = std::collections::HashMap::new();
+		let mut map = Values::new();
 		$(
-			map.insert($key, $value);
+			map.insert(String::from($key), Value::from($value));
 		)*
 		map
 	}}
@@ -37,16 +77,68 @@ struct BlogEntry {
 	url_name: String,
 	title: String,
 	description: String,
+	author: String,
 	date: DateTime<Utc>,
+	updated: Option<DateTime<Utc>>,
+	//Rendered HTML without comments, only used in feeds
+	content: String,
+	//Rendered HTML above the `<!--more-->` marker, empty without one
+	excerpt: String,
+	toc: String,
+	word_count: usize,
+	tags: Vec<String>,
+	draft: bool,
+	custom: BTreeMap<String, MetadataValue>,
+}
+
+impl BlogEntry {
+	fn last_modified(&self) -> DateTime<Utc> {
+		self.updated.unwrap_or(self.date)
+	}
+
+	//Rounded up to whole minutes, anything with words takes at least a minute
+	fn reading_minutes(&self) -> usize {
+		self.word_count.div_ceil(WORDS_PER_MINUTE)
+	}
+}
+
+#[derive(Default)]
+struct BlogEntries {
+	published: Vec<BlogEntry>,
+	//Future dated posts held back from this build
+	scheduled: Vec<BlogEntry>,
+}
+
+//Channel level details shared by all feed formats
+struct FeedChannel {
+	title: String,
+	description: String,
+	//Page the feed is about
+	link: String,
+	//URL of the directory the feed files are written to
+	feed_base: String,
+}
+
+struct TagGroup<'a> {
+	name: &'a str,
+	slug: String,
+	entries: Vec<&'a BlogEntry>,
 }
 
 #[derive(Debug)]
 struct Fragments {
+	dir: Option<PathBuf>,
 	css: String,
 	header: String,
 	footer: String,
 	blog_entry: String,
 	blog_list: String,
+	layout: String,
+	post: String,
+	tag: String,
+	tags: String,
+	sidenotes_css: String,
+	directives: HashMap<String, String>,
 }
 
 impl Fragments {
@@ -56,11 +148,18 @@ impl Fragments {
 
 			None => {
 				return Fragments {
+					dir: None,
 					css: String::new(),
 					header: String::new(),
 					footer: String::new(),
 					blog_entry: String::new(),
 					blog_list: String::new(),
+					layout: DEFAULT_LAYOUT_FRAGMENT.to_string(),
+					post: DEFAULT_POST_FRAGMENT.to_string(),
+					tag: String::new(),
+					tags: DEFAULT_TAGS_FRAGMENT.to_string(),
+					sidenotes_css: DEFAULT_SIDENOTES_CSS.to_string(),
+					directives: default_directives(),
 				};
 			}
 		};
@@ -81,18 +180,189 @@ impl Fragments {
 			fragment
 		}
 
-		let css = get_fragment(&mut dir, "style.css");
+		fn get_optional_fragment(dir: &mut PathBuf, name: &str, default: &str) -> String {
+			dir.push(name);
+			let exists = dir.exists();
+			dir.pop();
+
+			if exists {
+				get_fragment(dir, name)
+			} else {
+				default.to_string()
+			}
+		}
+
+		let mut css = get_fragment(&mut dir, "style.css");
+		//Exported by `--export-highlight-css` for class based highlighting
+		let highlight_css = get_optional_fragment(&mut dir, "highlight.css", "");
+		if !highlight_css.is_empty() {
+			css.push_str("\n\n");
+			css.push_str(&highlight_css);
+		}
 		let header = get_fragment(&mut dir, "header.html");
 		let footer = get_fragment(&mut dir, "footer.html");
 		let blog_entry = get_fragment(&mut dir, "blog_entry.html");
 		let blog_list = get_fragment(&mut dir, "blog_list.html");
+		let layout = get_optional_fragment(&mut dir, "layout.html", DEFAULT_LAYOUT_FRAGMENT);
+		let post = get_optional_fragment(&mut dir, "post.html", DEFAULT_POST_FRAGMENT);
+		//Tag pages fall back to looking like the main blog list
+		let tag = get_optional_fragment(&mut dir, "tag.html", &blog_list);
+		let tags = get_optional_fragment(&mut dir, "tags.html", DEFAULT_TAGS_FRAGMENT);
+		let sidenotes_css = get_optional_fragment(&mut dir, "sidenotes.css", DEFAULT_SIDENOTES_CSS);
+
+		//Every `directives/<name>.html` registers (or replaces) the directive `name`
+		let mut directives = default_directives();
+		dir.push("directives");
+		if let Ok(entries) = std::fs::read_dir(&dir) {
+			for entry in entries.flatten() {
+				let path = entry.path();
+				if path.extension().map(|p| p.to_str()) != Some(Some("html")) {
+					continue;
+				}
+
+				if let Some(Some(name)) = path.file_stem().map(|name| name.to_str()) {
+					let name = name.to_string();
+					let file_name = format!("{}.html", name);
+					directives.insert(name, get_fragment(&mut dir, &file_name));
+				}
+			}
+		}
+		dir.pop();
 
 		Fragments {
+			dir: Some(dir),
 			css,
 			header,
 			footer,
 			blog_entry,
 			blog_list,
+			layout,
+			post,
+			tag,
+			tags,
+			sidenotes_css,
+			directives,
+		}
+	}
+
+	fn include(&self, name: &str) -> String {
+		let dir = match &self.dir {
+			Some(dir) => dir,
+
+			None => {
+				eprintln!(
+					"Error including fragment '{}': no fragments directory was provided",
+					name
+				);
+				std::process::exit(-1);
+			}
+		};
+
+		match std::fs::read_to_string(dir.join(name)) {
+			Ok(fragment) => fragment.trim().to_string(),
+
+			Err(err) => {
+				eprintln!("Error loading included fragment '{}': {}", name, err);
+				std::process::exit(-1);
+			}
+		}
+	}
+}
+
+struct Metadata {
+	title: String,
+	description: String,
+	author: String,
+	date: String,
+	updated: String,
+	tags: Vec<String>,
+	draft: bool,
+	toc: bool,
+	markdown_extensions: Vec<String>,
+	custom: BTreeMap<String, MetadataValue>,
+}
+
+impl Metadata {
+	fn clear(&mut self) {
+		self.title.clear();
+		self.description.clear();
+		self.author.clear();
+		self.date.clear();
+		self.updated.clear();
+		self.tags.clear();
+		self.draft = false;
+		self.toc = true;
+		self.markdown_extensions.clear();
+		self.custom.clear();
+	}
+
+	/*
+	 * Shared by the HTML comment syntax and front matter, later values
+	 * replace earlier ones. Lists are joined for fields which only hold
+	 * a single piece of text. Unknown labels are kept as custom metadata.
+	 */
+	fn set(&mut self, label: &str, value: MetadataValue) {
+		if label == "tags" {
+			let tags = match value {
+				MetadataValue::Text(text) => text.split(',').map(str::to_string).collect(),
+				MetadataValue::List(list) => list,
+			};
+
+			self.tags.clear();
+			for tag in tags {
+				let tag = tag.trim();
+				if !tag.is_empty() && !self.tags.iter().any(|existing| existing == tag) {
+					self.tags.push(tag.to_string());
+				}
+			}
+			return;
+		}
+
+		if label == "draft" {
+			self.draft = match value {
+				MetadataValue::Text(text) => {
+					matches!(text.trim().to_lowercase().as_str(), "true" | "yes")
+				}
+				MetadataValue::List(_) => false,
+			};
+			return;
+		}
+
+		if label == "markdown_extensions" {
+			self.markdown_extensions = match value {
+				MetadataValue::Text(text) => text.split(',').map(str::to_string).collect(),
+				MetadataValue::List(list) => list,
+			};
+			return;
+		}
+
+		if label == "toc" {
+			self.toc = match value {
+				MetadataValue::Text(text) => {
+					!matches!(text.trim().to_lowercase().as_str(), "false" | "no")
+				}
+				MetadataValue::List(_) => true,
+			};
+			return;
+		}
+
+		let buffer = match label {
+			"title" => &mut self.title,
+			"description" => &mut self.description,
+			"author" => &mut self.author,
+			"date" => &mut self.date,
+			"updated" => &mut self.updated,
+
+			_ => {
+				self.custom.insert(label.to_string(), value);
+				return;
+			}
+		};
+
+		buffer.clear();
+		match value {
+			MetadataValue::Text(text) => buffer.push_str(text.trim()),
+			MetadataValue::List(list) => buffer.push_str(&list.join(", ")),
 		}
 	}
 }
@@ -100,172 +370,960 @@ impl Fragments {
 struct Buffers {
 	input: String,
 	html: String,
+	feed_html: String,
+	excerpt: String,
+	toc: String,
+	word_count: usize,
 	output: String,
 
-	title: String,
-	description: String,
-	author: String,
-	date: String,
+	metadata: Metadata,
+	highlighter: Option<Highlighter>,
 }
 
-fn process_markdown(fragments: &Fragments, args: &Arguments, buffers: &mut Buffers) {
+fn markdown_options(extensions: MarkdownExtensions) -> Options {
 	let mut options = Options::empty();
-	options.insert(Options::ENABLE_TABLES);
-	let parser = Parser::new_ext(&buffers.input, options);
+	options.set(Options::ENABLE_TABLES, extensions.tables);
+	options.set(Options::ENABLE_FOOTNOTES, extensions.footnotes);
+	options.set(Options::ENABLE_STRIKETHROUGH, extensions.strikethrough);
+	options.set(Options::ENABLE_TASKLISTS, extensions.tasklists);
+	options.set(
+		Options::ENABLE_SMART_PUNCTUATION,
+		extensions.smart_punctuation,
+	);
+	options.set(
+		Options::ENABLE_HEADING_ATTRIBUTES,
+		extensions.heading_attributes,
+	);
+	options.set(Options::ENABLE_MATH, extensions.math);
+	options
+}
+
+//The site wide extensions with the post's `markdown_extensions` override applied
+fn post_extensions(args: &Arguments, path: &Path, metadata: &Metadata) -> MarkdownExtensions {
+	let mut site_extensions = args.markdown_extensions.unwrap_or_default();
+	//Sidenotes are built from footnote definitions
+	site_extensions.footnotes |= args.sidenotes.unwrap_or(false);
+	if metadata.markdown_extensions.is_empty() {
+		return site_extensions;
+	}
+
+	let items: Vec<&str> = metadata
+		.markdown_extensions
+		.iter()
+		.map(String::as_str)
+		.collect();
+	match site_extensions.apply(&items) {
+		Ok(extensions) => extensions,
+
+		Err(err) => {
+			eprintln!(
+				"Error in markdown_extensions of '{}': {}",
+				path.to_string_lossy(),
+				err
+			);
+			std::process::exit(-1);
+		}
+	}
+}
+
+/*
+ * Ordinary comments like `<!-- left to do: images -->` or `<!-- TODO: fix -->`
+ * also contain a colon, so only identifier like labels count as metadata
+ * and all caps notes are left alone.
+ */
+fn is_metadata_label(label: &str) -> bool {
+	let starts_with_letter = label
+		.chars()
+		.next()
+		.map(|character| character.is_ascii_alphabetic())
+		.unwrap_or(false);
+	let is_identifier = label
+		.chars()
+		.all(|character| character.is_ascii_alphanumeric() || character == '_' || character == '-');
+	let is_all_caps = !label
+		.chars()
+		.any(|character| character.is_ascii_lowercase());
+
+	starts_with_letter && is_identifier && !is_all_caps
+}
+
+fn map_event<'a>(metadata: &mut Metadata, event: Event<'a>) -> Event<'a> {
+	if let Event::Html(html) = &event {
+		let html = html.trim();
+		if html.starts_with("<!--") && html.ends_with("-->") {
+			//We are reasonably confident that this is an HTML comment
+
+			let contents = &html["<!--".len()..];
+			let contents = &contents[..contents.len() - "-->".len()];
+
+			if let Some(colon_index) = contents.find(':') {
+				let label = contents[..colon_index].trim();
+				let trailing = contents[colon_index + 1..].trim();
+
+				if is_metadata_label(label) {
+					metadata.set(label, MetadataValue::Text(trailing.to_string()));
+				}
+			}
+		}
+	}
+
+	event
+}
+
+fn process_markdown(args: &Arguments, path: &Path, fragments: &Fragments, buffers: &mut Buffers) {
+	/*
+	 * NOTE: Borrowing this here borrows just the field instead of the entire
+	 * struct which allows the parser to have mutable access to the metadata
+	 * while the events it produces borrow the input.
+	 */
+	let metadata = &mut buffers.metadata;
 
 	/*
-	 * NOTE: Borrowing these here borrows just the field instead of the entire
-	 * struct which allows the closure to have mutable access to these two fields
-	 * while `html::push_html` writes to another field.
+	 * Front matter has already been applied so its extension override is
+	 * known up front. An override in a metadata comment is only seen while
+	 * parsing in which case the post is parsed again with the right options.
 	 */
-	let title_buffer = &mut buffers.title;
-	title_buffer.clear();
-	let description_buffer = &mut buffers.description;
-	description_buffer.clear();
-	let author_buffer = &mut buffers.author;
-	author_buffer.clear();
-	let date_buffer = &mut buffers.date;
-	date_buffer.clear();
+	let mut extensions = post_extensions(args, path, metadata);
+	let mut events = loop {
+		let parser = Parser::new_ext(&buffers.input, markdown_options(extensions));
+		let events: Vec<Event> = parser.map(|event| map_event(metadata, event)).collect();
+
+		let wanted = post_extensions(args, path, metadata);
+		if wanted == extensions {
+			break events;
+		}
+		extensions = wanted;
+	};
+
+	let context = MarkdownContext {
+		path,
+		fragments,
+		options: markdown_options(extensions),
+		highlighter: buffers.highlighter.as_ref(),
+		footnotes: events
+			.iter()
+			.filter_map(|event| match event {
+				Event::Start(Tag::FootnoteDefinition(label)) => Some(label.to_string()),
+				_ => None,
+			})
+			.collect(),
+	};
+	let directive_words = expand_directives(&context, metadata, &mut events);
+
+	if context.options.contains(Options::ENABLE_MATH) {
+		convert_math(&context, &mut events);
+	}
+
+	if let Some(highlighter) = context.highlighter {
+		highlight_code_blocks(highlighter, &mut events);
+	}
+
+	buffers.word_count = count_words(&events) + directive_words;
+
+	let post_name = path
+		.parent()
+		.and_then(Path::file_name)
+		.map(|name| slugify(&name.to_string_lossy()))
+		.unwrap_or_default();
+
+	if args.sidenotes.unwrap_or(false) {
+		convert_sidenotes(&post_name, &mut events);
+	}
+
+	/*
+	 * Everything before a top level `<!--more-->` comment becomes the excerpt.
+	 * Markers nested inside another block (a list, a quote) would split it
+	 * in half so they are ignored.
+	 */
+	let mut depth = 0usize;
+	let more = events.iter().position(|event| {
+		match event {
+			//The marker is itself wrapped in an HTML block tag which should not count
+			Event::Start(Tag::HtmlBlock) | Event::End(TagEnd::HtmlBlock) => {}
+			Event::Start(_) => depth += 1,
+			Event::End(_) => depth = depth.saturating_sub(1),
+			event if is_more_marker(event) => return depth == 0,
+			_ => {}
+		}
+		false
+	});
+
+	//Excerpts share the index page with other posts so their heading ids carry the post name
+	let mut excerpt = more.map(|more| events[..more].to_vec());
+	if let Some(excerpt) = &mut excerpt {
+		anchor_headings(args, &format!("{}-", post_name), excerpt);
+	}
+
+	let headings = anchor_headings(args, "", &mut events);
+	buffers.toc.clear();
+	if buffers.metadata.toc {
+		format_toc(&mut buffers.toc, &headings);
+	}
+
+	buffers.html.clear();
+	html::push_html(&mut buffers.html, events.iter().cloned());
+
+	//Metadata comments are only useful in the source, leave them out of feeds and summaries
+	buffers.feed_html.clear();
+	let events = events.iter().filter(|event| !is_comment(event));
+	html::push_html(&mut buffers.feed_html, events.cloned());
+
+	buffers.excerpt.clear();
+	if let Some(excerpt) = excerpt {
+		let events = excerpt.into_iter().filter(|event| !is_comment(event));
+		html::push_html(&mut buffers.excerpt, events);
+	}
+}
+
+fn is_more_marker(event: &Event) -> bool {
+	matches!(event, Event::Html(html) if html.trim() == "<!--more-->")
+}
+
+fn is_comment(event: &Event) -> bool {
+	match event {
+		Event::Html(html) => {
+			let html = html.trim();
+			html.starts_with("<!--") && html.ends_with("-->")
+		}
+		_ => false,
+	}
+}
+
+//Code is skimmed rather than read so it does not count towards reading time
+fn count_words(events: &[Event]) -> usize {
+	let mut in_code_block = false;
+	let mut word_count = 0;
+
+	for event in events {
+		match event {
+			Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+			Event::End(TagEnd::CodeBlock) => in_code_block = false,
+			Event::Text(text) if !in_code_block => word_count += text.split_whitespace().count(),
+			_ => {}
+		}
+	}
+
+	word_count
+}
+
+//Returns the text of the code block starting at `start` and the index of its end event
+fn code_block_text(events: &[Event], start: usize) -> (String, usize) {
+	let mut text = String::new();
+
+	let mut index = start + 1;
+	while index < events.len() && !matches!(events[index], Event::End(TagEnd::CodeBlock)) {
+		if let Event::Text(contents) = &events[index] {
+			text.push_str(contents);
+		}
+		index += 1;
+	}
+
+	(text, index.min(events.len() - 1))
+}
+
+/*
+ * Replaces a whole code block with a single raw HTML event. The other
+ * events stay in place but are emptied so indices remain valid.
+ */
+fn replace_code_block(events: &mut [Event], start: usize, end: usize, html: String) {
+	events[start] = Event::Html(html.into());
+	for event in &mut events[start + 1..=end] {
+		*event = Event::Text(CowStr::Borrowed(""));
+	}
+}
+
+fn remove_empty_footnotes(events: &mut Vec<Event>) {
+	let mut index = 0;
+	while index + 1 < events.len() {
+		let empty = matches!(events[index], Event::Start(Tag::FootnoteDefinition(_)))
+			&& matches!(events[index + 1], Event::End(TagEnd::FootnoteDefinition));
+		if empty {
+			events.drain(index..=index + 1);
+		} else {
+			index += 1;
+		}
+	}
+}
+
+fn fenced_info<'a>(event: &Event<'a>) -> Option<CowStr<'a>> {
+	match event {
+		Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => Some(info.clone()),
+		_ => None,
+	}
+}
+
+//Shared by the passes over a post's events and the markdown inside directives
+struct MarkdownContext<'a> {
+	path: &'a Path,
+	fragments: &'a Fragments,
+	options: Options,
+	highlighter: Option<&'a Highlighter>,
+	//Labels of the post's footnote definitions so directives can refer to them
+	footnotes: Vec<String>,
+}
+
+//Stands in for `$CONTENT$` so a directive's template can be split around its contents
+const CONTENT_MARKER: &str = "\u{1}CONTENT\u{1}";
+
+/*
+ * Fenced blocks whose info string starts with a registered directive name
+ * are rendered through that directive's template. The rest of the info
+ * string is available as `$TITLE$` and the block contents are rendered as
+ * markdown (including nested directives) into `$CONTENT$`.
+ *
+ * When the template uses `$CONTENT$` exactly once the contents are spliced
+ * back into the post's events between the rendered halves of the template,
+ * so math, highlighting, heading ids, the table of contents and footnotes
+ * treat them like any other part of the post. Otherwise (and for contents
+ * holding a `<!--more-->` marker) they are rendered on their own, in which
+ * case headings inside get no ids or table of contents entries. Footnotes
+ * defined in the post can be referred to from inside a directive but ones
+ * defined inside a directive only serve references in that directive.
+ * Returns the number of words in directives rendered that way since they
+ * no longer appear as text.
+ */
+fn expand_directives<'a>(
+	context: &MarkdownContext,
+	metadata: &mut Metadata,
+	events: &mut Vec<Event<'a>>,
+) -> usize {
+	let mut word_count = 0;
+
+	let mut start = 0;
+	while start < events.len() {
+		let info = match fenced_info(&events[start]) {
+			Some(info) => info,
+			None => {
+				start += 1;
+				continue;
+			}
+		};
+
+		let info = info.trim();
+		let (name, title) = match info.split_once(char::is_whitespace) {
+			Some((name, title)) => (name, title.trim()),
+			None => (info, ""),
+		};
+		let template = match context.fragments.directives.get(name) {
+			Some(template) => template,
+			None => {
+				start += 1;
+				continue;
+			}
+		};
+
+		let (contents, end) = code_block_text(events, start);
 
-	let parser = parser.map(|event| {
-		if let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(language))) = &event {
-			if *language == CowStr::Borrowed("image_description") {
-				return Event::Html(CowStr::Borrowed(r#"<div class="ImageDescription"><p>"#));
+		/*
+		 * A footnote reference only resolves when its definition is part of the
+		 * same source, so empty definitions for the post's footnotes are added
+		 * and removed again after parsing.
+		 */
+		let mut source = contents;
+		for label in &context.footnotes {
+			source.push_str(&format!("\n\n[^{}]:", label));
+		}
+
+		let parser = Parser::new_ext(&source, context.options);
+		let mut inner: Vec<Event<'a>> = parser
+			.map(|event| map_event(metadata, event).into_static())
+			.collect();
+		remove_empty_footnotes(&mut inner);
+		word_count += expand_directives(context, metadata, &mut inner);
+
+		let values = map![
+			"NAME" => name,
+			"TITLE" => escape_xml(title),
+			"CONTENT" => CONTENT_MARKER,
+		];
+		let includes = |name: &str| context.fragments.include(name);
+		let html = format_template(template, &values, &includes);
+
+		let halves = html
+			.split_once(CONTENT_MARKER)
+			.filter(|(_, after)| !after.contains(CONTENT_MARKER))
+			.filter(|_| !inner.iter().any(is_more_marker));
+
+		let replacement = match halves {
+			Some((before, after)) => {
+				let mut replacement = vec![Event::Html(before.to_string().into())];
+				replacement.append(&mut inner);
+				replacement.push(Event::Html(format!("{}\n", after).into()));
+				replacement
+			}
+
+			None => {
+				if context.options.contains(Options::ENABLE_MATH) {
+					convert_math(context, &mut inner);
+				}
+				if let Some(highlighter) = context.highlighter {
+					highlight_code_blocks(highlighter, &mut inner);
+				}
+				word_count += count_words(&inner);
+
+				let mut content = String::new();
+				html::push_html(&mut content, inner.into_iter());
+
+				let mut html = html.replace(CONTENT_MARKER, &content);
+				html.push('\n');
+				vec![Event::Html(html.into())]
 			}
+		};
+
+		let length = replacement.len();
+		events.splice(start..=end, replacement);
+		start += length;
+	}
+
+	word_count
+}
+
+/*
+ * Converts `$...$`, `$$...$$` and fenced `math` blocks to MathML. A formula
+ * the converter does not understand is shown as its LaTeX source instead of
+ * failing the whole build.
+ */
+fn convert_math(context: &MarkdownContext, events: &mut [Event]) {
+	let to_mathml = |source: &str, display: bool| match math::latex_to_mathml(source, display) {
+		Ok(mathml) => mathml,
+
+		Err(err) => {
+			eprintln!(
+				"Warning showing the source of math '{}' in '{}' as it could not be converted: {}",
+				source.trim(),
+				context.path.to_string_lossy(),
+				err
+			);
+			format!(r#"<code class="math">{}</code>"#, escape_xml(source.trim()))
 		}
+	};
 
-		if let Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(language))) = &event {
-			if *language == CowStr::Borrowed("image_description") {
-				return Event::Html(CowStr::Borrowed(r#"</p></div>"#));
+	for start in 0..events.len() {
+		match &events[start] {
+			Event::InlineMath(source) => {
+				events[start] = Event::Html(to_mathml(source, false).into())
 			}
+			Event::DisplayMath(source) => {
+				events[start] = Event::Html(to_mathml(source, true).into())
+			}
+
+			Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) if info.trim() == "math" => {
+				let (source, end) = code_block_text(events, start);
+				let mut html = to_mathml(&source, true);
+				html.push('\n');
+				replace_code_block(events, start, end, html);
+			}
+
+			_ => {}
 		}
+	}
+}
 
-		if let Event::Html(html) = &event {
-			let html = html.trim();
-			if html.starts_with("<!--") && html.ends_with("-->") {
-				//We are reasonably confident that this is an HTML comment
-
-				let contents = &html["<!--".len()..];
-				let contents = &contents[..contents.len() - "-->".len()];
-
-				if let Some(colon_index) = contents.find(':') {
-					let label = &contents[..colon_index];
-					let trailing = contents[colon_index + 1..].trim();
-
-					match label {
-						"title" => {
-							title_buffer.clear();
-							title_buffer.push_str(trailing);
-						}
-
-						"description" => {
-							description_buffer.clear();
-							description_buffer.push_str(trailing);
-						}
-
-						"author" => {
-							author_buffer.clear();
-							author_buffer.push_str(trailing);
-						}
-
-						"date" => {
-							date_buffer.clear();
-							date_buffer.push_str(trailing);
-						}
-
-						_ => {}
+//Fenced code blocks in a language syntect recognizes become highlighted HTML
+fn highlight_code_blocks(highlighter: &Highlighter, events: &mut [Event]) {
+	for start in 0..events.len() {
+		let info = match fenced_info(&events[start]) {
+			Some(info) => info,
+			None => continue,
+		};
+
+		let (code, end) = code_block_text(events, start);
+		if let Some(html) = highlighter.highlight(&info, &code) {
+			replace_code_block(events, start, end, html);
+		}
+	}
+}
+
+//Inline content of a sidenote split into lines, joined by `<br />` where blocks used to be
+struct SidenoteLines<'a> {
+	events: Vec<Event<'a>>,
+	line_started: bool,
+	pending_break: bool,
+	pending_bullet: bool,
+}
+
+impl<'a> SidenoteLines<'a> {
+	fn end_line(&mut self) {
+		self.pending_break |= self.line_started;
+		self.line_started = false;
+	}
+
+	fn push(&mut self, event: Event<'a>) {
+		if self.pending_break {
+			self.events.push(Event::Html(CowStr::Borrowed("<br />")));
+			self.pending_break = false;
+		}
+		if self.pending_bullet {
+			self.events.push(Event::Text(CowStr::Borrowed("• ")));
+			self.pending_bullet = false;
+		}
+		self.events.push(event);
+		self.line_started = true;
+	}
+}
+
+fn is_inline_tag(tag: &TagEnd) -> bool {
+	matches!(
+		tag,
+		TagEnd::Emphasis
+			| TagEnd::Strong
+			| TagEnd::Strikethrough
+			| TagEnd::Superscript
+			| TagEnd::Subscript
+			| TagEnd::Link
+			| TagEnd::Image
+	)
+}
+
+/*
+ * Sidenotes sit inside a paragraph where block elements are not allowed so
+ * the blocks of a footnote definition are flattened into lines. List items
+ * keep a bullet and every line of a code block becomes inline code.
+ */
+fn flatten_sidenote<'a>(events: &[Event<'a>]) -> Vec<Event<'a>> {
+	let mut lines = SidenoteLines {
+		events: Vec::new(),
+		line_started: false,
+		pending_break: false,
+		pending_bullet: false,
+	};
+	let mut in_code_block = false;
+
+	for event in events {
+		match event {
+			Event::Start(tag) if !is_inline_tag(&tag.to_end()) => {
+				lines.end_line();
+				match tag {
+					Tag::Item => lines.pending_bullet = true,
+					Tag::CodeBlock(_) => in_code_block = true,
+					_ => {}
+				}
+			}
+			Event::End(tag) if !is_inline_tag(tag) => {
+				lines.end_line();
+				in_code_block &= *tag != TagEnd::CodeBlock;
+			}
+			Event::Rule => lines.end_line(),
+
+			Event::Text(text) if in_code_block => {
+				for line in text.split_inclusive('\n') {
+					let code = line.trim_end_matches('\n');
+					if !code.is_empty() {
+						lines.push(Event::Code(code.to_string().into()));
+					}
+					if line.ends_with('\n') {
+						lines.end_line();
 					}
 				}
 			}
+
+			event => lines.push(event.clone()),
 		}
+	}
 
-		event
-	});
+	lines.events
+}
 
-	buffers.html.clear();
-	html::push_html(&mut buffers.html, parser);
-
-	buffers.output.clear();
-	buffers.output.push_str("<!DOCTYPE html>\n");
-	if let Some(language) = &args.language {
-		let _ = writeln!(buffers.output, r#"<html lang="{}">"#, language);
-	}
-	buffers.output.push_str(multiline!(
-		"\n<head>"
-		r#"<meta charset="UTF-8">"#
-	));
-	if !buffers.title.is_empty() {
-		let _ = writeln!(buffers.output, "<title>{}</title>", buffers.title);
-	}
-	if let Some(favicon) = &args.favicon {
-		let _ = writeln!(
-			buffers.output,
-			r#"<link rel="shortcut icon" type="image/png" href="{}" />"#,
-			favicon
+/*
+ * Moves each footnote definition next to its references as a sidenote.
+ * Every reference gets a label and hidden checkbox so narrow screens can
+ * toggle the note open with CSS alone. Ids include the post name since
+ * excerpts from several posts can share the index page.
+ */
+fn convert_sidenotes(post_name: &str, events: &mut [Event]) {
+	let mut definitions = HashMap::new();
+
+	let mut index = 0;
+	while index < events.len() {
+		let label = match &events[index] {
+			Event::Start(Tag::FootnoteDefinition(label)) => label.to_string(),
+			_ => {
+				index += 1;
+				continue;
+			}
+		};
+		let start = index;
+
+		while index < events.len()
+			&& !matches!(events[index], Event::End(TagEnd::FootnoteDefinition))
+		{
+			index += 1;
+		}
+		let end = index.min(events.len() - 1);
+
+		let mut note = String::new();
+		html::push_html(
+			&mut note,
+			flatten_sidenote(&events[start + 1..end]).into_iter(),
 		);
+		definitions.insert(label, note.trim().to_string());
+
+		for event in &mut events[start..=end] {
+			*event = Event::Text(CowStr::Borrowed(""));
+		}
+		index += 1;
 	}
-	if !buffers.description.is_empty() {
-		let _ = write!(
-			buffers.output,
-			multiline!(
-				r#"<meta name="description" content="{description}" />"#
-				r#"<meta property="og:description" content="{description}" />"#
-			),
-			description = buffers.description,
+
+	let mut numbers: HashMap<String, usize> = HashMap::new();
+	let mut reference_count = 0;
+	for event in events.iter_mut() {
+		let label = match event {
+			Event::FootnoteReference(label) => label.to_string(),
+			_ => continue,
+		};
+
+		let note = match definitions.get(&label) {
+			Some(note) => note,
+			None => {
+				*event = Event::Text(format!("[^{}]", label).into());
+				continue;
+			}
+		};
+
+		let next_number = numbers.len() + 1;
+		let number = *numbers.entry(label).or_insert(next_number);
+		reference_count += 1;
+		let id = format!("sidenote-{}-{}", post_name, reference_count);
+
+		*event = Event::Html(
+			format!(
+				concat!(
+					r#"<label for="{id}" class="SidenoteNumber">{number}</label>"#,
+					r#"<input type="checkbox" id="{id}" class="SidenoteToggle" />"#,
+					r#"<span class="Sidenote"><span class="SidenoteNumber">{number}</span>{note}</span>"#,
+				),
+				id = id,
+				number = number,
+				note = note,
+			)
+			.into(),
 		);
 	}
-	if !buffers.author.is_empty() {
-		let _ = writeln!(
-			buffers.output,
-			r#"<meta name="author" content="{}" />"#,
-			buffers.author
-		);
+}
+
+struct Heading {
+	level: u32,
+	slug: String,
+	text: String,
+}
+
+/*
+ * Replaces the start and end of every heading with raw HTML carrying an id
+ * generated from the heading text, unless the author gave one with the
+ * heading attributes extension. Generated ids are kept unique within the
+ * page by appending a counter. Returns the headings in document order.
+ */
+fn anchor_headings(args: &Arguments, id_prefix: &str, events: &mut [Event]) -> Vec<Heading> {
+	let heading_links = args.heading_links.unwrap_or(false);
+
+	let mut headings = Vec::new();
+	let mut used_slugs = HashSet::new();
+
+	let mut index = 0;
+	while index < events.len() {
+		let (level, id, attributes) = match &events[index] {
+			Event::Start(Tag::Heading {
+				level,
+				id,
+				classes,
+				attrs,
+			}) => {
+				let mut attributes = String::new();
+				if !classes.is_empty() {
+					let classes: Vec<&str> = classes.iter().map(|class| class.as_ref()).collect();
+					attributes.push_str(&format!(r#" class="{}""#, escape_xml(&classes.join(" "))));
+				}
+				//Custom attributes from `{data-x=1}` heading attribute blocks
+				for (name, value) in attrs {
+					match value {
+						Some(value) => attributes.push_str(&format!(
+							r#" {}="{}""#,
+							escape_xml(name),
+							escape_xml(value)
+						)),
+						None => attributes.push_str(&format!(" {}", escape_xml(name))),
+					}
+				}
+
+				(
+					*level as u32,
+					id.as_ref().map(|id| id.to_string()),
+					attributes,
+				)
+			}
+			_ => {
+				index += 1;
+				continue;
+			}
+		};
+		let start = index;
+
+		let mut text = String::new();
+		while index < events.len() && !matches!(events[index], Event::End(TagEnd::Heading(_))) {
+			if let Event::Text(contents) | Event::Code(contents) = &events[index] {
+				text.push_str(contents);
+			}
+			index += 1;
+		}
+
+		let slug = match id {
+			Some(id) => {
+				used_slugs.insert(id.clone());
+				escape_xml(&id)
+			}
+
+			None => {
+				let base_slug = match slugify(&text) {
+					slug if slug.is_empty() => String::from("section"),
+					slug => slug,
+				};
+				let mut slug = base_slug.clone();
+				let mut counter = 1;
+				while !used_slugs.insert(slug.clone()) {
+					slug = format!("{}-{}", base_slug, counter);
+					counter += 1;
+				}
+				slug
+			}
+		};
+
+		//Self-links in excerpts are rewritten to the post page where the id has no prefix
+		events[start] =
+			Event::Html(format!(r#"<h{} id="{}{}"{}>"#, level, id_prefix, slug, attributes).into());
+		if index < events.len() {
+			let link = if heading_links {
+				format!(r##" <a class="HeadingLink" href="#{}">#</a>"##, slug)
+			} else {
+				String::new()
+			};
+			events[index] = Event::Html(format!("{}</h{}>\n", link, level).into());
+		}
+		index += 1;
+
+		headings.push(Heading { level, slug, text });
 	}
-	if let Some(opengraph_locale) = &args.opengraph_locale {
-		let _ = writeln!(
-			buffers.output,
-			r#"<meta property="og:locale" content="{}" />"#,
-			opengraph_locale
-		);
+
+	headings
+}
+
+//Nested lists follow the heading levels, skipped levels do not add empty lists
+fn format_toc(toc: &mut String, headings: &[Heading]) {
+	let mut open_levels: Vec<u32> = Vec::new();
+
+	for heading in headings {
+		while let Some(&open) = open_levels.last() {
+			if open <= heading.level {
+				break;
+			}
+			toc.push_str("</li>\n</ul>\n");
+			open_levels.pop();
+		}
+
+		match open_levels.last() {
+			Some(&open) if open == heading.level => toc.push_str("</li>\n<li>"),
+			Some(_) => {
+				toc.push_str("\n<ul>\n<li>");
+				open_levels.push(heading.level);
+			}
+			None => {
+				toc.push_str("<ul>\n<li>");
+				open_levels.push(heading.level);
+			}
+		}
+
+		write!(
+			toc,
+			r##"<a href="#{}">{}</a>"##,
+			heading.slug,
+			escape_xml(&heading.text)
+		)
+		.unwrap();
 	}
-	if let Some(opengraph_sitename) = &args.opengraph_sitename {
-		let _ = writeln!(
-			buffers.output,
-			r#"<meta property="og:site_name" content="{}" />"#,
-			opengraph_sitename
-		);
+
+	for _ in open_levels {
+		toc.push_str("</li>\n</ul>\n");
 	}
+}
 
-	if !fragments.css.is_empty() {
-		buffers.output.push_str("<style>\n");
-		buffers.output.push_str(&fragments.css);
-		buffers.output.push_str("</style>\n");
+fn format_display_date(args: &Arguments, date: &DateTime<Utc>) -> String {
+	let language = args.language.as_deref().unwrap_or("en");
+	let format = args
+		.date_format
+		.as_deref()
+		.unwrap_or_else(|| dates::default_format(language));
+
+	//Display dates in the site's UTC offset rather than UTC
+	let offset = args.utc_offset.unwrap_or_else(|| Utc.fix());
+	dates::format_date(&date.with_timezone(&offset), format, language)
+}
+
+fn default_directives() -> HashMap<String, String> {
+	DEFAULT_DIRECTIVES
+		.iter()
+		.map(|(name, template)| (name.to_string(), template.to_string()))
+		.collect()
+}
+
+fn site_values(fragments: &Fragments, args: &Arguments) -> Values {
+	let mut css = fragments.css.clone();
+	if args.sidenotes.unwrap_or(false) {
+		css.push_str("\n\n");
+		css.push_str(&fragments.sidenotes_css);
 	}
 
-	buffers.output.push_str("</head>\n\n");
+	map![
+		"BASE_URL" => args.blog_base_url.as_str(),
+		"SITE_TITLE" => site_title(args),
+		"SITE_DESCRIPTION" => site_description(args),
+		"LANGUAGE" => args.language.clone().unwrap_or_default(),
+		"FAVICON" => args.favicon.clone().unwrap_or_default(),
+		"OPENGRAPH_LOCALE" => args.opengraph_locale.clone().unwrap_or_default(),
+		"OPENGRAPH_SITENAME" => args.opengraph_sitename.clone().unwrap_or_default(),
+		"CSS" => css,
+		"HEADER" => fragments.header.as_str(),
+		"FOOTER" => fragments.footer.as_str(),
+	]
+}
 
-	if !fragments.header.is_empty() {
-		buffers.output.push_str(&fragments.header);
-		buffers.output.push_str("\n\n");
+/*
+ * Renders `template` with the given values and then wraps the result
+ * as `$CONTENT$` in the shared page layout.
+ */
+fn format_page(fragments: &Fragments, template: &str, mut template_values: Values) -> String {
+	let includes = |name: &str| fragments.include(name);
+
+	let content = format_template(template, &template_values, &includes);
+	template_values.insert(String::from("CONTENT"), Value::from(content));
+	format_template(&fragments.layout, &template_values, &includes)
+}
+
+//Custom metadata label `cover image` becomes the template key `META_COVER_IMAGE`
+fn custom_metadata_key(label: &str) -> String {
+	let mut key = String::from("META_");
+
+	for character in label.chars() {
+		if character.is_alphanumeric() {
+			key.extend(character.to_uppercase());
+		} else {
+			key.push('_');
+		}
 	}
 
-	buffers.output.push_str(&buffers.html);
+	key
+}
 
-	if !fragments.footer.is_empty() {
-		buffers.output.push_str("\n\n");
-		buffers.output.push_str(&fragments.footer);
+fn slugify(text: &str) -> String {
+	let mut slug = String::new();
+
+	for character in text.trim().chars() {
+		if character.is_alphanumeric() {
+			slug.extend(character.to_lowercase());
+		} else if !slug.ends_with('-') {
+			slug.push('-');
+		}
 	}
+
+	slug.trim_matches('-').to_string()
 }
 
-fn process_file(
-	args: &Arguments,
-	path: &Path,
-	output_path: PathBuf,
-	url_name: &str,
-	fragments: &Fragments,
-	buffers: &mut Buffers,
-	blog_entries: &mut Vec<BlogEntry>,
-) {
+/*
+ * Symbols which tell tags like "C", "C++" and "C#" apart are spelled out
+ * before slugifying. A tag made only of other symbols is named after their
+ * code points so its page never lands on the tag overview.
+ */
+fn tag_slug(tag: &str) -> String {
+	let mut spelled = String::with_capacity(tag.len());
+	for character in tag.chars() {
+		match character {
+			'+' => spelled.push_str(" plus "),
+			'#' => spelled.push_str(" sharp "),
+			'&' => spelled.push_str(" and "),
+			'@' => spelled.push_str(" at "),
+			character => spelled.push(character),
+		}
+	}
+
+	match slugify(&spelled) {
+		slug if slug.is_empty() => {
+			let code_points: Vec<String> = tag
+				.trim()
+				.chars()
+				.map(|character| format!("u{:x}", character as u32))
+				.collect();
+			code_points.join("-")
+		}
+		slug => slug,
+	}
+}
+
+fn tag_link(args: &Arguments, tag: &str) -> String {
+	format!("{}/tags/{}", args.blog_base_url, tag_slug(tag))
+}
+
+fn entry_values(args: &Arguments, entry: &BlogEntry) -> Values {
+	let tags: Vec<Values> = entry
+		.tags
+		.iter()
+		.map(|tag| {
+			map![
+				"NAME" => tag.as_str(),
+				"SLUG" => tag_slug(tag),
+				"LINK" => tag_link(args, tag),
+			]
+		})
+		.collect();
+
+	let mut template_values = map![
+		"TITLE" => entry.title.as_str(),
+		"DESCRIPTION" => entry.description.as_str(),
+		"AUTHOR" => entry.author.as_str(),
+		"DATE" => format_display_date(args, &entry.date),
+		"DATE_ISO" => entry.date.to_rfc3339(),
+		"UPDATED" => entry
+			.updated
+			.map(|updated| format_display_date(args, &updated))
+			.unwrap_or_default(),
+		"UPDATED_ISO" => entry.updated.map(|updated| updated.to_rfc3339()).unwrap_or_default(),
+		"LINK" => format!("{}/{}", args.blog_base_url, entry.url_name),
+		"URL_NAME" => entry.url_name.as_str(),
+		"EXCERPT" => absolutize_urls(
+			&entry.excerpt,
+			&format!("{}/{}", args.blog_base_url, entry.url_name)
+		),
+		"TAGS" => tags,
+		"DRAFT" => entry.draft,
+		"TOC" => entry.toc.as_str(),
+		"WORD_COUNT" => entry.word_count.to_string(),
+		"READING_TIME" => entry.reading_minutes().to_string(),
+	];
+
+	for (label, value) in &entry.custom {
+		let value = match value {
+			MetadataValue::Text(text) => Value::from(text.as_str()),
+			MetadataValue::List(list) => Value::from(
+				list.iter()
+					.map(|item| Value::from(item.as_str()))
+					.collect::<Vec<_>>(),
+			),
+		};
+		template_values.insert(custom_metadata_key(label), value);
+	}
+
+	template_values
+}
+
+fn format_post(fragments: &Fragments, args: &Arguments, entry: &BlogEntry, buffers: &mut Buffers) {
+	let mut template_values = site_values(fragments, args);
+	template_values.extend(entry_values(args, entry));
+	template_values.extend(map![
+		"PAGE" => "post",
+		"CONTENT" => buffers.html.as_str(),
+	]);
+
+	buffers.output = format_page(fragments, &fragments.post, template_values);
+}
+
+fn create_parent_dir(output_path: &Path) {
 	if let Some(dir_path) = output_path.parent() {
 		/*
 		 * NOTE: Silently swallow failure to create output path.
@@ -276,11 +1334,26 @@ fn process_file(
 		 */
 		let _ = std::fs::create_dir_all(dir_path);
 	}
+}
 
+/*
+ * Returns false if the file is a post which should not be published, in
+ * which case the rest of its folder should be skipped as well.
+ */
+fn process_file(
+	args: &Arguments,
+	path: &Path,
+	output_path: PathBuf,
+	url_name: &str,
+	fragments: &Fragments,
+	buffers: &mut Buffers,
+	blog_entries: &mut BlogEntries,
+) -> bool {
 	let is_markdown = path.extension().map(|p| p.to_str()) == Some(Some("md"));
 
 	if !is_markdown {
-		if let Err(err) = std::fs::copy(&path, &output_path) {
+		create_parent_dir(&output_path);
+		if let Err(err) = std::fs::copy(path, &output_path) {
 			eprintln!(
 				"Error copying input file '{}' to '{}': {}",
 				path.to_string_lossy(),
@@ -290,7 +1363,7 @@ fn process_file(
 			std::process::exit(-1);
 		}
 	} else {
-		let mut file = match File::open(&path) {
+		let mut file = match File::open(path) {
 			Ok(file) => file,
 
 			Err(err) => {
@@ -313,7 +1386,35 @@ fn process_file(
 			std::process::exit(-1);
 		}
 
-		process_markdown(fragments, args, buffers);
+		buffers.metadata.clear();
+		match front_matter::parse(&buffers.input) {
+			Ok(Some(front_matter)) => {
+				for warning in &front_matter.warnings {
+					eprintln!(
+						"Warning in front matter of '{}': {}",
+						path.to_string_lossy(),
+						warning
+					);
+				}
+				for (label, value) in front_matter.fields {
+					buffers.metadata.set(&label, value);
+				}
+				buffers.input.drain(..front_matter.length);
+			}
+
+			Ok(None) => {}
+
+			Err(err) => {
+				eprintln!(
+					"Error parsing front matter in input file '{}': {}",
+					path.to_string_lossy(),
+					err
+				);
+				std::process::exit(-1);
+			}
+		}
+
+		process_markdown(args, path, fragments, buffers);
 
 		fn check_error<'a>(text: &'a str, attribute: &str, path: &Path) -> &'a str {
 			if text.is_empty() {
@@ -328,30 +1429,84 @@ fn process_file(
 			}
 		}
 
-		let title = check_error(&buffers.title, "title", &path).to_string();
-		let description = check_error(&buffers.description, "description", &path).to_string();
+		let title = check_error(&buffers.metadata.title, "title", path).to_string();
+		let description =
+			check_error(&buffers.metadata.description, "description", path).to_string();
 
-		let date = check_error(&buffers.date, "date", &path);
-		let date = match DateTime::parse_from_str(date, "%d %b %Y %H:%M:%S %z") {
-			Ok(date) => date,
-			Err(err) => {
-				eprintln!(
-					"Error parsing date attribute in input file '{}': {}",
-					path.to_string_lossy(),
-					err
-				);
-				std::process::exit(-1);
+		let default_offset = args.utc_offset.unwrap_or_else(|| Utc.fix());
+		let parse_date = |text: &str, attribute: &str| -> DateTime<Utc> {
+			match dates::parse_date(text, default_offset) {
+				Some(date) => date,
+				None => {
+					eprintln!(
+						"Error parsing {} attribute '{}' in input file '{}', accepted formats are {}",
+						attribute,
+						text,
+						path.to_string_lossy(),
+						dates::ACCEPTED_FORMATS
+					);
+					std::process::exit(-1);
+				}
+			}
+		};
+
+		/*
+		 * NOTE: Missing dates fall back to the git history of the post. The
+		 * commit which added `content.md` is the publish date and any later
+		 * commit touching the post folder counts as an update.
+		 */
+		let git_added = if buffers.metadata.date.is_empty() {
+			git::first_added(path)
+		} else {
+			None
+		};
+
+		let date = match git_added {
+			Some(added) => added,
+
+			None => {
+				let date = check_error(&buffers.metadata.date, "date", path);
+				parse_date(date, "date")
 			}
 		};
 
+		let updated = if buffers.metadata.updated.is_empty() {
+			path.parent()
+				.and_then(git::last_modified)
+				.filter(|modified| *modified > date)
+		} else {
+			Some(parse_date(&buffers.metadata.updated, "updated"))
+		};
+
 		let blog_entry = BlogEntry {
 			url_name: url_name.to_string(),
 			title,
 			description,
-			date: date.into(),
+			author: buffers.metadata.author.clone(),
+			date,
+			updated,
+			content: buffers.feed_html.clone(),
+			excerpt: buffers.excerpt.clone(),
+			toc: buffers.toc.clone(),
+			word_count: buffers.word_count,
+			tags: std::mem::take(&mut buffers.metadata.tags),
+			draft: buffers.metadata.draft,
+			custom: std::mem::take(&mut buffers.metadata.custom),
 		};
-		blog_entries.push(blog_entry);
 
+		if blog_entry.draft && !args.drafts.unwrap_or(false) {
+			return false;
+		}
+
+		if blog_entry.date > Utc::now() && !args.future.unwrap_or(false) {
+			blog_entries.scheduled.push(blog_entry);
+			return false;
+		}
+
+		format_post(fragments, args, &blog_entry, buffers);
+		blog_entries.published.push(blog_entry);
+
+		create_parent_dir(&output_path);
 		if let Err(err) = std::fs::write(&output_path, &buffers.output) {
 			eprintln!(
 				"Error writing HTML to path '{}': {}",
@@ -361,6 +1516,8 @@ fn process_file(
 			std::process::exit(-1);
 		}
 	}
+
+	true
 }
 
 fn process_dir(
@@ -369,7 +1526,7 @@ fn process_dir(
 	dir_path: &Path,
 	fragments: &Fragments,
 	buffers: &mut Buffers,
-	blog_entries: &mut Vec<BlogEntry>,
+	blog_entries: &mut BlogEntries,
 ) {
 	let url_name = folder_name.to_string_lossy();
 	let dir = match std::fs::read_dir(dir_path) {
@@ -385,53 +1542,10 @@ fn process_dir(
 		}
 	};
 
+	let mut file_paths = Vec::new();
 	for entry in dir {
 		match entry {
-			Ok(entry) => {
-				let file_path = entry.path();
-				let file_name = file_path.file_name().unwrap_or_else(|| {
-					eprintln!(
-						"Failed to get filename for '{}'",
-						file_path.to_string_lossy()
-					);
-					std::process::exit(-1);
-				});
-				let extension = file_path
-					.extension()
-					.map(|e| e.to_str())
-					.unwrap_or(Some(""))
-					.unwrap_or("");
-
-				let output_path = {
-					let mut output_path = args.output_dir.clone();
-					output_path.push(folder_name);
-
-					if extension == "md" {
-						if file_name != "content.md" {
-							eprintln!(
-								"Error, markdown file '{}' is not named 'content.md'",
-								file_path.to_string_lossy()
-							);
-							std::process::exit(-1);
-						}
-						output_path.push("index.html");
-					} else {
-						output_path.push(file_name);
-					}
-
-					output_path
-				};
-
-				process_file(
-					args,
-					&file_path,
-					output_path,
-					&url_name,
-					fragments,
-					buffers,
-					blog_entries,
-				);
-			}
+			Ok(entry) => file_paths.push(entry.path()),
 
 			Err(err) => {
 				eprintln!(
@@ -443,81 +1557,724 @@ fn process_dir(
 			}
 		}
 	}
+
+	//Process the markdown first so an unpublished post skips copying its assets
+	file_paths.sort_by_key(|path| path.extension() != Some(OsStr::new("md")));
+
+	for file_path in file_paths {
+		let file_name = file_path.file_name().unwrap_or_else(|| {
+			eprintln!(
+				"Failed to get filename for '{}'",
+				file_path.to_string_lossy()
+			);
+			std::process::exit(-1);
+		});
+		let extension = file_path
+			.extension()
+			.map(|e| e.to_str())
+			.unwrap_or(Some(""))
+			.unwrap_or("");
+
+		let output_path = {
+			let mut output_path = args.output_dir.clone();
+			output_path.push(folder_name);
+
+			if extension == "md" {
+				if file_name != "content.md" {
+					eprintln!(
+						"Error, markdown file '{}' is not named 'content.md'",
+						file_path.to_string_lossy()
+					);
+					std::process::exit(-1);
+				}
+				output_path.push("index.html");
+			} else {
+				output_path.push(file_name);
+			}
+
+			output_path
+		};
+
+		let published = process_file(
+			args,
+			&file_path,
+			output_path,
+			&url_name,
+			fragments,
+			buffers,
+			blog_entries,
+		);
+		if !published {
+			return;
+		}
+	}
+}
+
+fn escape_xml(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+
+	for character in text.chars() {
+		match character {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&apos;"),
+			_ => escaped.push(character),
+		}
+	}
+
+	escaped
+}
+
+fn site_title(args: &Arguments) -> String {
+	args.site_title
+		.clone()
+		.or_else(|| args.opengraph_sitename.clone())
+		.unwrap_or_else(|| args.blog_base_url.clone())
+}
+
+fn site_description(args: &Arguments) -> String {
+	args.site_description
+		.clone()
+		.unwrap_or_else(|| site_title(args))
+}
+
+/*
+ * RSS wants an RFC 1766 language tag such as `en-us`, fall back to
+ * deriving one from the Open Graph locale (`en_US`) when no language
+ * was provided.
+ */
+fn feed_language(args: &Arguments) -> Option<String> {
+	args.language
+		.clone()
+		.or_else(|| args.opengraph_locale.clone())
+		.map(|language| language.replace('_', "-").to_lowercase())
+}
+
+/*
+ * Feed readers display content detached from the post page so relative
+ * `href`/`src` values would resolve against the wrong location. Rewrites
+ * them to absolute URLs under the post's URL (or the site origin for
+ * root-relative values).
+ */
+fn absolutize_urls(html: &str, post_url: &str) -> String {
+	let origin = match post_url.find("://") {
+		Some(scheme_end) => {
+			let host_start = scheme_end + "://".len();
+			match post_url[host_start..].find('/') {
+				Some(path_start) => &post_url[..host_start + path_start],
+				None => post_url,
+			}
+		}
+		None => "",
+	};
+
+	let mut output = String::with_capacity(html.len());
+	let mut remaining = html;
+
+	loop {
+		let next = ["href=", "src="]
+			.iter()
+			.filter_map(|attribute| {
+				remaining
+					.find(attribute)
+					.map(|index| (index, index + attribute.len()))
+			})
+			.filter(|(index, _)| {
+				*index > 0 && remaining.as_bytes()[*index - 1].is_ascii_whitespace()
+			})
+			.min();
+
+		let (value_start, quote) = match next {
+			Some((_, value_start)) => match remaining.as_bytes().get(value_start) {
+				Some(quote @ b'"') | Some(quote @ b'\'') => (value_start + 1, *quote as char),
+				_ => {
+					output.push_str(&remaining[..value_start]);
+					remaining = &remaining[value_start..];
+					continue;
+				}
+			},
+
+			None => break,
+		};
+
+		output.push_str(&remaining[..value_start]);
+		remaining = &remaining[value_start..];
+
+		let value_end = remaining.find(quote).unwrap_or(remaining.len());
+		let value = &remaining[..value_end];
+
+		let is_absolute = value.starts_with("//")
+			|| value
+				.find(':')
+				.map(|colon| {
+					value[..colon]
+						.chars()
+						.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+				})
+				.unwrap_or(false);
+
+		if is_absolute {
+			output.push_str(value);
+		} else if value.starts_with('/') {
+			output.push_str(origin);
+			output.push_str(value);
+		} else if value.starts_with('#') {
+			output.push_str(post_url);
+			output.push_str(value);
+		} else {
+			output.push_str(post_url);
+			output.push('/');
+			output.push_str(value.trim_start_matches("./"));
+		}
+
+		remaining = &remaining[value_end..];
+	}
+
+	output.push_str(remaining);
+	output
+}
+
+fn cdata(text: &str) -> String {
+	format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
 }
 
-fn format_rss(args: &Arguments, blog_entries: &[BlogEntry]) -> String {
+fn format_rss(args: &Arguments, channel: &FeedChannel, blog_entries: &[&BlogEntry]) -> String {
+	let include_content = args.feed_content.unwrap_or(false);
+
 	let items = {
 		let mut items = String::new();
 
 		for entry in blog_entries {
+			let link = format!("{}/{}", args.blog_base_url, entry.url_name);
+
 			write!(
 				items,
 				multiline!(
 					"<item>"
+					"	<title>{title}</title>"
+					"	<link>{link}</link>"
+					r#"	<guid isPermaLink="true">{link}</guid>"#
 					"	<description>{description}</description>"
 					"	<pubDate>{date}</pubDate>"
-					"	<link>{base_url}/{url_name}</link>"
-					"</item>"
 				),
-				description = entry.description,
+				title = escape_xml(&entry.title),
+				link = escape_xml(&link),
+				description = if entry.excerpt.is_empty() {
+					escape_xml(&entry.description)
+				} else {
+					escape_xml(&absolutize_urls(&entry.excerpt, &link))
+				},
 				date = entry.date.to_rfc2822(),
-				base_url = args.blog_base_url,
-				url_name = entry.url_name,
 			)
 			.unwrap();
+
+			/*
+			 * NOTE: RSS requires `<author>` to be an email address,
+			 * plain names go in the Dublin Core creator element instead.
+			 */
+			if entry.author.contains('@') {
+				writeln!(items, "	<author>{}</author>", escape_xml(&entry.author)).unwrap();
+			} else if !entry.author.is_empty() {
+				writeln!(
+					items,
+					"	<dc:creator>{}</dc:creator>",
+					escape_xml(&entry.author)
+				)
+				.unwrap();
+			}
+
+			if include_content {
+				writeln!(
+					items,
+					"	<content:encoded>{}</content:encoded>",
+					cdata(&absolutize_urls(&entry.content, &link))
+				)
+				.unwrap();
+			}
+
+			items.push_str("</item>\n");
 		}
 
 		items
 	};
 
+	let language = match feed_language(args) {
+		Some(language) => format!("<language>{}</language>\n", escape_xml(&language)),
+		None => String::new(),
+	};
+
+	let now = Utc::now().to_rfc2822();
+
 	let rss = format!(
 		multiline!(
-			r#"<?xml version="1.0"?>"#
+			r#"<?xml version="1.0" encoding="UTF-8"?>"#
 			"<!--RSS generated {date} by floc_blog {version}-->"
-			r#"<rss version="2.0">"#
-			"<language>{language}</language>"
-			"<generator>floc_blog {version}</generator>"
+			r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:content="http://purl.org/rss/1.0/modules/content/">"#
 			r#"<channel>"#
-			"\n{items}"
-			r#"</channel>"#
+			"<title>{title}</title>"
+			"<link>{link}</link>"
+			"<description>{description}</description>"
+			"{language}<generator>floc_blog {version}</generator>"
+			"<lastBuildDate>{date}</lastBuildDate>"
+			r#"<atom:link href="{self_link}" rel="self" type="application/rss+xml" />"#
+			"{items}</channel>"
 			r#"</rss>"#
 		),
-		date = Utc::now().to_rfc2822(),
+		date = now,
 		version = VERSION,
-		language = args.language.clone().unwrap_or_else(|| "en_US".to_string()),
+		title = escape_xml(&channel.title),
+		link = escape_xml(&channel.link),
+		description = escape_xml(&channel.description),
+		language = language,
+		self_link = escape_xml(&format!("{}/feed.rss", channel.feed_base)),
 		items = items,
 	);
 
 	rss
 }
 
-fn format_blog_list(
-	args: &Arguments,
-	blog_entries: Vec<BlogEntry>,
-	fragments: Fragments,
-) -> String {
-	let formatted_entries = {
-		let mut formatted_entries = String::new();
+fn format_atom(args: &Arguments, channel: &FeedChannel, blog_entries: &[&BlogEntry]) -> String {
+	let include_content = args.feed_content.unwrap_or(false);
+
+	let entries = {
+		let mut entries = String::new();
 
 		for entry in blog_entries {
 			let link = format!("{}/{}", args.blog_base_url, entry.url_name);
-			let template_values = map![
-				"TITLE" => entry.title,
-				"DESCRIPTION" => entry.description,
-				"DATE" => format!("{}", entry.date.format("%A the %eth of %B %Y")),
-				"LINK" => link,
-			];
 
-			let formatted = format_template(fragments.blog_entry.clone(), template_values);
-			formatted_entries.push_str(&formatted);
+			write!(
+				entries,
+				multiline!(
+					"<entry>"
+					r#"	<title type="text">{title}</title>"#
+					r#"	<link href="{link}" rel="alternate" type="text/html" />"#
+					"	<id>{link}</id>"
+					"	<published>{date}</published>"
+					"	<updated>{updated}</updated>"
+					r#"	<summary type="{summary_type}">{summary}</summary>"#
+				),
+				title = escape_xml(&entry.title),
+				link = escape_xml(&link),
+				date = entry.date.to_rfc3339(),
+				updated = entry.last_modified().to_rfc3339(),
+				summary_type = if entry.excerpt.is_empty() {
+					"text"
+				} else {
+					"html"
+				},
+				summary = if entry.excerpt.is_empty() {
+					escape_xml(&entry.description)
+				} else {
+					escape_xml(&absolutize_urls(&entry.excerpt, &link))
+				},
+			)
+			.unwrap();
+
+			if !entry.author.is_empty() {
+				writeln!(
+					entries,
+					"	<author><name>{}</name></author>",
+					escape_xml(&entry.author)
+				)
+				.unwrap();
+			}
+
+			if include_content {
+				writeln!(
+					entries,
+					r#"	<content type="html">{}</content>"#,
+					escape_xml(&absolutize_urls(&entry.content, &link))
+				)
+				.unwrap();
+			}
+
+			entries.push_str("</entry>\n");
+		}
+
+		entries
+	};
+
+	let language = match feed_language(args) {
+		Some(language) => format!(r#" xml:lang="{}""#, escape_xml(&language)),
+		None => String::new(),
+	};
+
+	let updated = blog_entries
+		.iter()
+		.map(|entry| entry.last_modified())
+		.max()
+		.unwrap_or_else(Utc::now);
+
+	format!(
+		multiline!(
+			r#"<?xml version="1.0" encoding="UTF-8"?>"#
+			"<!--Atom generated {now} by floc_blog {version}-->"
+			r#"<feed xmlns="http://www.w3.org/2005/Atom"{language}>"#
+			"<title>{title}</title>"
+			"<subtitle>{description}</subtitle>"
+			"<id>{link}</id>"
+			r#"<link href="{link}" rel="alternate" type="text/html" />"#
+			r#"<link href="{self_link}" rel="self" type="application/atom+xml" />"#
+			"<updated>{updated}</updated>"
+			"<author><name>{title}</name></author>"
+			r#"<generator version="{version}">floc_blog</generator>"#
+			"{entries}</feed>"
+		),
+		now = Utc::now().to_rfc3339(),
+		version = VERSION,
+		language = language,
+		title = escape_xml(&channel.title),
+		description = escape_xml(&channel.description),
+		link = escape_xml(&channel.link),
+		self_link = escape_xml(&format!("{}/atom.xml", channel.feed_base)),
+		updated = updated.to_rfc3339(),
+		entries = entries,
+	)
+}
+
+//JSON Feed summaries are plain text so the excerpt loses its markup
+fn plain_text(html: &str) -> String {
+	let mut text = String::with_capacity(html.len());
+	let mut in_tag = false;
+
+	for character in html.chars() {
+		match character {
+			'<' => in_tag = true,
+			'>' if in_tag => {
+				in_tag = false;
+				text.push(' ');
+			}
+			character if !in_tag => text.push(character),
+			_ => {}
+		}
+	}
+
+	let text = text
+		.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&#39;", "'")
+		.replace("&amp;", "&");
+	text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn json_string(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len() + 2);
+	escaped.push('"');
+
+	for character in text.chars() {
+		match character {
+			'"' => escaped.push_str("\\\""),
+			'\\' => escaped.push_str("\\\\"),
+			'\n' => escaped.push_str("\\n"),
+			'\r' => escaped.push_str("\\r"),
+			'\t' => escaped.push_str("\\t"),
+			character if (character as u32) < 0x20 => {
+				let _ = write!(escaped, "\\u{:04x}", character as u32);
+			}
+			_ => escaped.push(character),
+		}
+	}
+
+	escaped.push('"');
+	escaped
+}
+
+fn format_json_feed(
+	args: &Arguments,
+	channel: &FeedChannel,
+	blog_entries: &[&BlogEntry],
+) -> String {
+	let mut items = Vec::new();
+
+	for entry in blog_entries {
+		let link = format!("{}/{}", args.blog_base_url, entry.url_name);
+
+		let mut item = String::new();
+		write!(
+			item,
+			multiline!(
+				"		{{"
+				r#"			"id": {link},"#
+				r#"			"url": {link},"#
+				r#"			"title": {title},"#
+				r#"			"summary": {description},"#
+				r#"			"content_html": {content},"#
+			),
+			link = json_string(&link),
+			title = json_string(&entry.title),
+			description = if entry.excerpt.is_empty() {
+				json_string(&entry.description)
+			} else {
+				json_string(&plain_text(&entry.excerpt))
+			},
+			content = json_string(&absolutize_urls(&entry.content, &link)),
+		)
+		.unwrap();
+
+		if !entry.author.is_empty() {
+			writeln!(
+				item,
+				r#"			"authors": [{{ "name": {} }}],"#,
+				json_string(&entry.author)
+			)
+			.unwrap();
+		}
+
+		if !entry.tags.is_empty() {
+			let tags: Vec<String> = entry.tags.iter().map(|tag| json_string(tag)).collect();
+			writeln!(item, r#"			"tags": [{}],"#, tags.join(", ")).unwrap();
+		}
+
+		//Custom JSON Feed extensions must start with an underscore
+		writeln!(
+			item,
+			r#"			"_reading": {{ "word_count": {}, "minutes": {} }},"#,
+			entry.word_count,
+			entry.reading_minutes()
+		)
+		.unwrap();
+
+		if let Some(updated) = entry.updated {
+			writeln!(
+				item,
+				r#"			"date_modified": {},"#,
+				json_string(&updated.to_rfc3339())
+			)
+			.unwrap();
+		}
+
+		write!(
+			item,
+			r#"			"date_published": {}"#,
+			json_string(&entry.date.to_rfc3339())
+		)
+		.unwrap();
+		item.push_str("\n		}");
+
+		items.push(item);
+	}
+
+	let language = match &args.language {
+		Some(language) => format!("\t\"language\": {},\n", json_string(language)),
+		None => String::new(),
+	};
+
+	format!(
+		multiline!(
+			"{{"
+			r#"	"version": "https://jsonfeed.org/version/1.1","#
+			r#"	"title": {title},"#
+			r#"	"description": {description},"#
+			r#"	"home_page_url": {link},"#
+			r#"	"feed_url": {self_link},"#
+			"{language}	\"items\": ["
+			"{items}"
+			"	]"
+			"}}"
+		),
+		title = json_string(&channel.title),
+		description = json_string(&channel.description),
+		link = json_string(&channel.link),
+		self_link = json_string(&format!("{}/feed.json", channel.feed_base)),
+		language = language,
+		items = items.join(",\n"),
+	)
+}
+
+fn format_sitemap(args: &Arguments, blog_entries: &[BlogEntry], tag_groups: &[TagGroup]) -> String {
+	let mut urls = String::new();
+
+	let mut push_url = |link: &str, last_modified: Option<DateTime<Utc>>| {
+		urls.push_str("<url>\n");
+		writeln!(urls, "	<loc>{}</loc>", escape_xml(link)).unwrap();
+		if let Some(last_modified) = last_modified {
+			writeln!(urls, "	<lastmod>{}</lastmod>", last_modified.to_rfc3339()).unwrap();
+		}
+		urls.push_str("</url>\n");
+	};
+
+	let newest_change = blog_entries.iter().map(BlogEntry::last_modified).max();
+	push_url(&args.blog_base_url, newest_change);
+
+	for entry in blog_entries {
+		let link = format!("{}/{}", args.blog_base_url, entry.url_name);
+		push_url(&link, Some(entry.last_modified()));
+	}
+
+	for group in tag_groups {
+		let link = tag_link(args, group.name);
+		let newest_change = group
+			.entries
+			.iter()
+			.map(|entry| entry.last_modified())
+			.max();
+		push_url(&link, newest_change);
+	}
+
+	format!(
+		multiline!(
+			r#"<?xml version="1.0" encoding="UTF-8"?>"#
+			r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#
+			"{urls}</urlset>"
+		),
+		urls = urls,
+	)
+}
+
+fn write_output(args: &Arguments, relative_path: &str, contents: &str) {
+	let output_path = args.output_dir.join(relative_path);
+
+	if let Some(dir_path) = output_path.parent() {
+		//NOTE: Silently swallow failure, the write will catch any real problem
+		let _ = std::fs::create_dir_all(dir_path);
+	}
+
+	if let Err(err) = std::fs::write(&output_path, contents) {
+		eprintln!(
+			"Error writing output file '{}': {}",
+			output_path.to_string_lossy(),
+			err
+		);
+		std::process::exit(-1);
+	}
+}
+
+/*
+ * Writes every enabled feed format into `relative_dir` of the output
+ * directory, an empty `relative_dir` being the output root.
+ */
+fn write_feeds(
+	args: &Arguments,
+	relative_dir: &str,
+	channel: &FeedChannel,
+	blog_entries: &[&BlogEntry],
+) {
+	let feed_formats = args.feed_formats.clone().unwrap_or_default();
+	let relative_path = |file_name: &str| -> String {
+		if relative_dir.is_empty() {
+			file_name.to_string()
+		} else {
+			format!("{}/{}", relative_dir, file_name)
 		}
-		formatted_entries
 	};
 
-	let template_values = map![
+	if feed_formats.rss {
+		let rss = format_rss(args, channel, blog_entries);
+		write_output(args, &relative_path("feed.rss"), &rss);
+	}
+	if feed_formats.atom {
+		let atom = format_atom(args, channel, blog_entries);
+		write_output(args, &relative_path("atom.xml"), &atom);
+	}
+	if feed_formats.json {
+		let json = format_json_feed(args, channel, blog_entries);
+		write_output(args, &relative_path("feed.json"), &json);
+	}
+}
+
+fn list_values(args: &Arguments, fragments: &Fragments, blog_entries: &[&BlogEntry]) -> Values {
+	let includes = |name: &str| fragments.include(name);
+
+	let mut formatted_entries = String::new();
+	let mut posts = Vec::new();
+
+	for entry in blog_entries {
+		let template_values = entry_values(args, entry);
+
+		let formatted = format_template(&fragments.blog_entry, &template_values, &includes);
+		formatted_entries.push_str(&formatted);
+		posts.push(template_values);
+	}
+
+	map![
 		"ENTRIES" => formatted_entries,
-	];
-	format_template(fragments.blog_list, template_values)
+		"POSTS" => posts,
+	]
+}
+
+fn format_blog_list(args: &Arguments, blog_entries: &[BlogEntry], fragments: &Fragments) -> String {
+	let blog_entries: Vec<&BlogEntry> = blog_entries.iter().collect();
+
+	let mut template_values = site_values(fragments, args);
+	template_values.extend(list_values(args, fragments, &blog_entries));
+	template_values.extend(map![
+		"PAGE" => "index",
+		"TITLE" => site_title(args),
+		"DESCRIPTION" => args.site_description.clone().unwrap_or_default(),
+		"LINK" => args.blog_base_url.as_str(),
+	]);
+
+	format_page(fragments, &fragments.blog_list, template_values)
+}
+
+//Groups entries by tag slug, keeping the newest first order of `blog_entries`
+fn collect_tags(blog_entries: &[BlogEntry]) -> Vec<TagGroup<'_>> {
+	let mut groups: BTreeMap<String, TagGroup> = BTreeMap::new();
+	let mut merged = HashSet::new();
+
+	for entry in blog_entries {
+		for tag in &entry.tags {
+			let slug = tag_slug(tag);
+			let group = groups.entry(slug.clone()).or_insert_with(|| TagGroup {
+				name: tag,
+				slug,
+				entries: Vec::new(),
+			});
+
+			//Tags only differing in case share a page quietly, mention anything else
+			let differs = group.name.to_lowercase() != tag.to_lowercase();
+			if differs && merged.insert((group.name, tag.as_str())) {
+				eprintln!(
+					"Warning tags '{}' and '{}' share the page 'tags/{}/'",
+					group.name, tag, group.slug
+				);
+			}
+			group.entries.push(entry);
+		}
+	}
+
+	groups.into_values().collect()
+}
+
+fn format_tag_page(args: &Arguments, group: &TagGroup, fragments: &Fragments) -> String {
+	let mut template_values = site_values(fragments, args);
+	template_values.extend(list_values(args, fragments, &group.entries));
+	template_values.extend(map![
+		"PAGE" => "tag",
+		"TITLE" => group.name,
+		"TAG" => group.name,
+		"TAG_SLUG" => group.slug.as_str(),
+		"LINK" => tag_link(args, group.name),
+	]);
+
+	format_page(fragments, &fragments.tag, template_values)
+}
+
+fn format_tags_overview(args: &Arguments, groups: &[TagGroup], fragments: &Fragments) -> String {
+	let tags: Vec<Values> = groups
+		.iter()
+		.map(|group| {
+			map![
+				"NAME" => group.name,
+				"SLUG" => group.slug.as_str(),
+				"LINK" => tag_link(args, group.name),
+				"COUNT" => group.entries.len().to_string(),
+			]
+		})
+		.collect();
+
+	let mut template_values = site_values(fragments, args);
+	template_values.extend(map![
+		"PAGE" => "tags",
+		"TITLE" => "Tags",
+		"LINK" => format!("{}/tags", args.blog_base_url),
+		"TAGS" => tags,
+	]);
+
+	format_page(fragments, &fragments.tags, template_values)
 }
 
 fn main() {
@@ -548,16 +2305,32 @@ fn main() {
 	 */
 	let _ = std::fs::remove_dir_all(&args.output_dir);
 
-	let mut blog_entries = Vec::new();
+	let mut blog_entries = BlogEntries::default();
 
 	let mut buffers = Buffers {
 		input: String::new(),
 		html: String::new(),
+		feed_html: String::new(),
+		excerpt: String::new(),
+		toc: String::new(),
+		word_count: 0,
 		output: String::new(),
-		title: String::new(),
-		description: String::new(),
-		author: String::new(),
-		date: String::new(),
+		metadata: Metadata {
+			title: String::new(),
+			description: String::new(),
+			author: String::new(),
+			date: String::new(),
+			updated: String::new(),
+			tags: Vec::new(),
+			draft: false,
+			toc: true,
+			markdown_extensions: Vec::new(),
+			custom: BTreeMap::new(),
+		},
+		highlighter: args.highlight.map(|mode| {
+			let theme = args.highlight_theme.as_deref();
+			Highlighter::new(mode, theme.unwrap_or(highlight::DEFAULT_THEME))
+		}),
 	};
 
 	for entry in input_dir {
@@ -605,37 +2378,139 @@ fn main() {
 		}
 	}
 
-	blog_entries.sort_by(|left, right| right.date.cmp(&left.date));
-
-	{
-		let rss = format_rss(&args, &blog_entries);
-
-		let mut output_path = args.output_dir.clone();
-		output_path.push("feed.rss");
+	if !blog_entries.scheduled.is_empty() {
+		blog_entries.scheduled.sort_by_key(|entry| entry.date);
 
-		if let Err(err) = std::fs::write(&output_path, &rss) {
-			eprintln!(
-				"Error writing RSS feed file'{}': {}",
-				output_path.to_string_lossy(),
-				err
+		println!(
+			"Held back {} future dated post(s), pass --future to publish them now:",
+			blog_entries.scheduled.len()
+		);
+		for entry in &blog_entries.scheduled {
+			println!(
+				"    '{}' ({}) scheduled for {}",
+				entry.title,
+				entry.url_name,
+				entry.date.to_rfc2822()
 			);
-			std::process::exit(-1);
 		}
 	}
 
+	let mut blog_entries = blog_entries.published;
+	blog_entries.sort_by_key(|entry| std::cmp::Reverse(entry.date));
+
 	{
-		let list_page = format_blog_list(&args, blog_entries, fragments);
+		let channel = FeedChannel {
+			title: site_title(&args),
+			description: site_description(&args),
+			link: args.blog_base_url.clone(),
+			feed_base: args.blog_base_url.clone(),
+		};
 
-		let mut output_path = args.output_dir;
-		output_path.push("index.html");
+		let all_entries: Vec<&BlogEntry> = blog_entries.iter().collect();
+		write_feeds(&args, "", &channel, &all_entries);
+	}
 
-		if let Err(err) = std::fs::write(&output_path, &list_page) {
-			eprintln!(
-				"Error writing blog entry list '{}': {}",
-				output_path.to_string_lossy(),
-				err
-			);
+	let tag_groups = collect_tags(&blog_entries);
+	if !tag_groups.is_empty() {
+		if blog_entries.iter().any(|entry| entry.url_name == "tags") {
+			eprintln!("Error, a post folder named 'tags' conflicts with the generated tag pages");
 			std::process::exit(-1);
 		}
+
+		for group in &tag_groups {
+			let relative_dir = format!("tags/{}", group.slug);
+
+			let tag_page = format_tag_page(&args, group, &fragments);
+			write_output(&args, &format!("{}/index.html", relative_dir), &tag_page);
+
+			let channel = FeedChannel {
+				title: format!("{} - {}", site_title(&args), group.name),
+				description: format!("Posts tagged '{}' on {}", group.name, site_title(&args)),
+				link: tag_link(&args, group.name),
+				feed_base: tag_link(&args, group.name),
+			};
+			write_feeds(&args, &relative_dir, &channel, &group.entries);
+		}
+
+		let overview = format_tags_overview(&args, &tag_groups, &fragments);
+		write_output(&args, "tags/index.html", &overview);
+	}
+
+	let sitemap = format_sitemap(&args, &blog_entries, &tag_groups);
+	write_output(&args, "sitemap.xml", &sitemap);
+
+	let list_page = format_blog_list(&args, &blog_entries, &fragments);
+	write_output(&args, "index.html", &list_page);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tag_slugs_keep_symbols_apart() {
+		assert_eq!(tag_slug("C"), "c");
+		assert_eq!(tag_slug("C++"), "c-plus-plus");
+		assert_eq!(tag_slug("C#"), "c-sharp");
+		assert_eq!(tag_slug("F#"), "f-sharp");
+		assert_eq!(tag_slug("R&D"), "r-and-d");
+		assert_eq!(tag_slug("Release Notes"), "release-notes");
+		assert_eq!(tag_slug("Rust"), tag_slug("rust"));
+	}
+
+	#[test]
+	fn tag_slugs_are_never_empty() {
+		assert_eq!(tag_slug("++"), "plus-plus");
+		assert_eq!(tag_slug("!?"), "u21-u3f");
+		assert_eq!(tag_slug(" ~ "), "u7e");
+	}
+
+	fn expand(markdown: &str) -> Vec<Event<'_>> {
+		let fragments = Fragments::retrive_or_shim(None);
+		let mut metadata = Metadata {
+			title: String::new(),
+			description: String::new(),
+			author: String::new(),
+			date: String::new(),
+			updated: String::new(),
+			tags: Vec::new(),
+			draft: false,
+			toc: true,
+			markdown_extensions: Vec::new(),
+			custom: BTreeMap::new(),
+		};
+
+		let options = Options::ENABLE_FOOTNOTES;
+		let mut events: Vec<Event> = Parser::new_ext(markdown, options).collect();
+		let context = MarkdownContext {
+			path: Path::new("content.md"),
+			fragments: &fragments,
+			options,
+			highlighter: None,
+			footnotes: vec!["a".to_string()],
+		};
+		expand_directives(&context, &mut metadata, &mut events);
+		events
+	}
+
+	#[test]
+	fn directive_contents_stay_in_the_event_stream() {
+		let events = expand("```note Title\n## Inside\n\nSee[^a].\n```\n\n[^a]: Footnote.\n");
+
+		assert!(
+			matches!(&events[0], Event::Html(html) if html.starts_with("<div class=\"Note\">"))
+		);
+		assert!(events
+			.iter()
+			.any(|event| matches!(event, Event::Start(Tag::Heading { .. }))));
+		assert!(events.iter().any(
+			|event| matches!(event, Event::FootnoteReference(label) if label.as_ref() == "a")
+		));
+
+		let definitions = events
+			.iter()
+			.filter(|event| matches!(event, Event::Start(Tag::FootnoteDefinition(_))))
+			.count();
+		assert_eq!(definitions, 1);
 	}
 }
@@ -0,0 +1,1005 @@
+use crate::escape_xml;
+
+/*
+ * Converts the commonly used subset of LaTeX math into MathML so formulas
+ * render without any client side JavaScript. Supported are the usual
+ * letters, numbers and operators, `^`/`_` scripts, groups, `\frac`,
+ * `\sqrt`, `\binom`, `\overset`, `\not`, accents, font commands, `\text`,
+ * `\left`/`\right`, `\big` and friends, spacing commands and the matrix,
+ * cases and aligned environments. Anything else is reported as an error
+ * rather than silently dropped so the caller can fall back to the source.
+ */
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+	Char(char),
+	Command(String),
+	Open,
+	Close,
+	Superscript,
+	Subscript,
+	Ampersand,
+	RowBreak,
+}
+
+//What ended a row of nodes
+#[derive(Debug, PartialEq)]
+enum Stop {
+	End,
+	Close,
+	Right,
+	Ampersand,
+	RowBreak,
+	EndEnvironment,
+}
+
+struct Node {
+	mathml: String,
+	//Scripts on large operators go above and below in display math
+	large_operator: bool,
+}
+
+impl Node {
+	fn new(mathml: String) -> Node {
+		Node {
+			mathml,
+			large_operator: false,
+		}
+	}
+}
+
+const GREEK: &[(&str, &str)] = &[
+	("alpha", "α"),
+	("beta", "β"),
+	("gamma", "γ"),
+	("delta", "δ"),
+	("epsilon", "ϵ"),
+	("varepsilon", "ε"),
+	("zeta", "ζ"),
+	("eta", "η"),
+	("theta", "θ"),
+	("vartheta", "ϑ"),
+	("iota", "ι"),
+	("kappa", "κ"),
+	("lambda", "λ"),
+	("mu", "μ"),
+	("nu", "ν"),
+	("xi", "ξ"),
+	("pi", "π"),
+	("varpi", "ϖ"),
+	("rho", "ρ"),
+	("varrho", "ϱ"),
+	("sigma", "σ"),
+	("varsigma", "ς"),
+	("tau", "τ"),
+	("upsilon", "υ"),
+	("phi", "ϕ"),
+	("varphi", "φ"),
+	("chi", "χ"),
+	("psi", "ψ"),
+	("omega", "ω"),
+	("Gamma", "Γ"),
+	("Delta", "Δ"),
+	("Theta", "Θ"),
+	("Lambda", "Λ"),
+	("Xi", "Ξ"),
+	("Pi", "Π"),
+	("Sigma", "Σ"),
+	("Upsilon", "Υ"),
+	("Phi", "Φ"),
+	("Psi", "Ψ"),
+	("Omega", "Ω"),
+];
+
+//Symbols which are identifiers rather than operators
+const IDENTIFIERS: &[(&str, &str)] = &[
+	("infty", "∞"),
+	("partial", "∂"),
+	("nabla", "∇"),
+	("emptyset", "∅"),
+	("varnothing", "∅"),
+	("hbar", "ℏ"),
+	("ell", "ℓ"),
+	("Re", "ℜ"),
+	("Im", "ℑ"),
+	("aleph", "ℵ"),
+];
+
+const OPERATORS: &[(&str, &str)] = &[
+	("times", "×"),
+	("cdot", "⋅"),
+	("div", "÷"),
+	("pm", "±"),
+	("mp", "∓"),
+	("ast", "∗"),
+	("star", "⋆"),
+	("circ", "∘"),
+	("bullet", "∙"),
+	("leq", "≤"),
+	("le", "≤"),
+	("geq", "≥"),
+	("ge", "≥"),
+	("neq", "≠"),
+	("ne", "≠"),
+	("ll", "≪"),
+	("gg", "≫"),
+	("approx", "≈"),
+	("equiv", "≡"),
+	("sim", "∼"),
+	("simeq", "≃"),
+	("cong", "≅"),
+	("propto", "∝"),
+	("in", "∈"),
+	("notin", "∉"),
+	("ni", "∋"),
+	("subset", "⊂"),
+	("subseteq", "⊆"),
+	("supset", "⊃"),
+	("supseteq", "⊇"),
+	("cup", "∪"),
+	("cap", "∩"),
+	("setminus", "∖"),
+	("land", "∧"),
+	("wedge", "∧"),
+	("lor", "∨"),
+	("vee", "∨"),
+	("neg", "¬"),
+	("lnot", "¬"),
+	("forall", "∀"),
+	("exists", "∃"),
+	("to", "→"),
+	("rightarrow", "→"),
+	("leftarrow", "←"),
+	("gets", "←"),
+	("leftrightarrow", "↔"),
+	("Rightarrow", "⇒"),
+	("implies", "⇒"),
+	("Leftarrow", "⇐"),
+	("Leftrightarrow", "⇔"),
+	("iff", "⇔"),
+	("mapsto", "↦"),
+	("uparrow", "↑"),
+	("downarrow", "↓"),
+	("mid", "∣"),
+	("parallel", "∥"),
+	("perp", "⊥"),
+	("angle", "∠"),
+	("oplus", "⊕"),
+	("otimes", "⊗"),
+	("ldots", "…"),
+	("dots", "…"),
+	("cdots", "⋯"),
+	("vdots", "⋮"),
+	("ddots", "⋱"),
+	("langle", "⟨"),
+	("rangle", "⟩"),
+	("lfloor", "⌊"),
+	("rfloor", "⌋"),
+	("lceil", "⌈"),
+	("rceil", "⌉"),
+	("lbrace", "{"),
+	("rbrace", "}"),
+	("vert", "|"),
+	("Vert", "‖"),
+	("lvert", "|"),
+	("rvert", "|"),
+	("lVert", "‖"),
+	("rVert", "‖"),
+	("prime", "′"),
+];
+
+const LARGE_OPERATORS: &[(&str, &str)] = &[
+	("sum", "∑"),
+	("prod", "∏"),
+	("coprod", "∐"),
+	("int", "∫"),
+	("iint", "∬"),
+	("iiint", "∭"),
+	("oint", "∮"),
+	("bigcup", "⋃"),
+	("bigcap", "⋂"),
+	("bigoplus", "⨁"),
+	("bigotimes", "⨂"),
+];
+
+//Named operators which take limits below them in display math
+const LIMIT_FUNCTIONS: &[&str] = &[
+	"lim", "liminf", "limsup", "max", "min", "sup", "inf", "det", "gcd", "Pr", "argmax", "argmin",
+];
+
+const FUNCTIONS: &[&str] = &[
+	"sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+	"coth", "log", "ln", "lg", "exp", "deg", "dim", "ker", "arg", "hom", "mod",
+];
+
+const ACCENTS: &[(&str, &str)] = &[
+	("hat", "^"),
+	("widehat", "^"),
+	("bar", "¯"),
+	("overline", "¯"),
+	("vec", "→"),
+	("overrightarrow", "→"),
+	("dot", "˙"),
+	("ddot", "¨"),
+	("tilde", "~"),
+	("widetilde", "~"),
+	("overbrace", "⏞"),
+];
+
+const UNDER_ACCENTS: &[(&str, &str)] = &[("underline", "_"), ("underbrace", "⏟")];
+
+const FONTS: &[(&str, &str)] = &[
+	("mathrm", "normal"),
+	("mathbf", "bold"),
+	("boldsymbol", "bold-italic"),
+	("mathit", "italic"),
+	("mathbb", "double-struck"),
+	("mathcal", "script"),
+	("mathscr", "script"),
+	("mathfrak", "fraktur"),
+	("mathsf", "sans-serif"),
+	("mathtt", "monospace"),
+];
+
+//Fixed sizes of `\big(` and friends, the `l`, `r` and `m` variants only differ in spacing
+const DELIMITER_SIZES: &[(&str, &str)] = &[
+	("big", "1.2em"),
+	("Big", "1.8em"),
+	("bigg", "2.4em"),
+	("Bigg", "3em"),
+];
+
+//Negations with their own character, others get a combining long solidus
+const NEGATIONS: &[(&str, &str)] = &[
+	("=", "≠"),
+	("∈", "∉"),
+	("≡", "≢"),
+	("⊂", "⊄"),
+	("⊆", "⊈"),
+	("⊃", "⊅"),
+	("⊇", "⊉"),
+	("∼", "≁"),
+	("≅", "≇"),
+	("<", "≮"),
+	(">", "≯"),
+	("≤", "≰"),
+	("≥", "≱"),
+	("∣", "∤"),
+	("∥", "∦"),
+];
+
+const SPACES: &[(&str, &str)] = &[
+	(",", "0.1667em"),
+	("thinspace", "0.1667em"),
+	(":", "0.2222em"),
+	(">", "0.2222em"),
+	("medspace", "0.2222em"),
+	(";", "0.2778em"),
+	("thickspace", "0.2778em"),
+	(" ", "0.25em"),
+	("quad", "1em"),
+	("qquad", "2em"),
+	("!", "-0.1667em"),
+];
+
+fn lookup(table: &[(&str, &'static str)], name: &str) -> Option<&'static str> {
+	table
+		.iter()
+		.find(|(entry, _)| *entry == name)
+		.map(|(_, value)| *value)
+}
+
+fn mrow(nodes: Vec<Node>) -> String {
+	let contents: String = nodes.into_iter().map(|node| node.mathml).collect();
+	format!("<mrow>{}</mrow>", contents)
+}
+
+//Like `mrow` but a single node is not wrapped
+fn row(nodes: Vec<Node>) -> String {
+	match nodes.len() {
+		1 => nodes.into_iter().next().unwrap().mathml,
+		_ => mrow(nodes),
+	}
+}
+
+fn operator(text: &str) -> String {
+	format!("<mo>{}</mo>", escape_xml(text))
+}
+
+fn fence(text: &str) -> String {
+	match text {
+		"" | "." => String::new(),
+		text => format!(
+			r#"<mo fence="true" stretchy="true">{}</mo>"#,
+			escape_xml(text)
+		),
+	}
+}
+
+struct Parser<'a> {
+	source: &'a str,
+	index: usize,
+	display: bool,
+}
+
+impl<'a> Parser<'a> {
+	fn skip_whitespace(&mut self) {
+		let remaining = &self.source[self.index..];
+		self.index += remaining.len() - remaining.trim_start().len();
+	}
+
+	fn next_token(&mut self) -> Option<Token> {
+		self.skip_whitespace();
+
+		let mut characters = self.source[self.index..].chars();
+		let character = characters.next()?;
+		self.index += character.len_utf8();
+
+		let token = match character {
+			'{' => Token::Open,
+			'}' => Token::Close,
+			'^' => Token::Superscript,
+			'_' => Token::Subscript,
+			'&' => Token::Ampersand,
+
+			'\\' => {
+				let name: String = characters
+					.clone()
+					.take_while(|character| character.is_ascii_alphabetic())
+					.collect();
+
+				if name.is_empty() {
+					match characters.next() {
+						Some('\\') => {
+							self.index += 1;
+							Token::RowBreak
+						}
+						Some(other) => {
+							self.index += other.len_utf8();
+							Token::Command(other.to_string())
+						}
+						None => Token::Char('\\'),
+					}
+				} else {
+					self.index += name.len();
+					Token::Command(name)
+				}
+			}
+
+			character => Token::Char(character),
+		};
+
+		Some(token)
+	}
+
+	fn peek_token(&mut self) -> Option<Token> {
+		let index = self.index;
+		let token = self.next_token();
+		self.index = index;
+		token
+	}
+
+	//Raw contents of a `{...}` group, used for text which keeps its spaces
+	fn raw_group(&mut self) -> Result<String, String> {
+		self.skip_whitespace();
+		if !self.source[self.index..].starts_with('{') {
+			return Err("expected '{' after text command".to_string());
+		}
+		self.index += 1;
+
+		let mut depth = 0;
+		for (offset, character) in self.source[self.index..].char_indices() {
+			match character {
+				'{' => depth += 1,
+				'}' if depth == 0 => {
+					let text = self.source[self.index..self.index + offset].to_string();
+					self.index += offset + 1;
+					return Ok(text);
+				}
+				'}' => depth -= 1,
+				_ => {}
+			}
+		}
+
+		Err("unclosed '{'".to_string())
+	}
+
+	//The name inside `{...}` after `\begin` or `\end`
+	fn environment_name(&mut self) -> Result<String, String> {
+		Ok(self.raw_group()?.trim().to_string())
+	}
+
+	fn parse_row(&mut self) -> Result<(Vec<Node>, Stop), String> {
+		let mut nodes = Vec::new();
+
+		loop {
+			let token = match self.peek_token() {
+				Some(token) => token,
+				None => return Ok((nodes, Stop::End)),
+			};
+
+			let stop = match &token {
+				Token::Close => Some(Stop::Close),
+				Token::Ampersand => Some(Stop::Ampersand),
+				Token::RowBreak => Some(Stop::RowBreak),
+				Token::Command(name) if name == "right" => Some(Stop::Right),
+				Token::Command(name) if name == "end" => Some(Stop::EndEnvironment),
+				_ => None,
+			};
+			if let Some(stop) = stop {
+				self.next_token();
+				return Ok((nodes, stop));
+			}
+
+			let base = match token {
+				Token::Superscript | Token::Subscript => Node::new(String::from("<mrow></mrow>")),
+				_ => self.parse_atom(false)?,
+			};
+			nodes.push(self.parse_scripts(base)?);
+		}
+	}
+
+	fn parse_scripts(&mut self, base: Node) -> Result<Node, String> {
+		let mut superscript = None;
+		let mut subscript = None;
+
+		loop {
+			match self.peek_token() {
+				Some(Token::Superscript) if superscript.is_none() => {
+					self.next_token();
+					superscript = Some(self.parse_atom(true)?.mathml);
+				}
+				Some(Token::Subscript) if subscript.is_none() => {
+					self.next_token();
+					subscript = Some(self.parse_atom(true)?.mathml);
+				}
+				Some(Token::Char('\'')) if superscript.is_none() => {
+					let mut primes = String::new();
+					while let Some(Token::Char('\'')) = self.peek_token() {
+						self.next_token();
+						primes.push('′');
+					}
+					superscript = Some(operator(&primes));
+				}
+				_ => break,
+			}
+		}
+
+		let (under, over, both) = if base.large_operator && self.display {
+			("munder", "mover", "munderover")
+		} else {
+			("msub", "msup", "msubsup")
+		};
+
+		let mathml = match (subscript, superscript) {
+			(None, None) => return Ok(base),
+			(Some(sub), None) => format!("<{0}>{1}{2}</{0}>", under, base.mathml, sub),
+			(None, Some(sup)) => format!("<{0}>{1}{2}</{0}>", over, base.mathml, sup),
+			(Some(sub), Some(sup)) => format!("<{0}>{1}{2}{3}</{0}>", both, base.mathml, sub, sup),
+		};
+		Ok(Node::new(mathml))
+	}
+
+	/*
+	 * Parses a single atom. As an argument (of a command or script) a run
+	 * of digits only contributes its first digit, as in `\frac12` or `x^23`.
+	 */
+	fn parse_atom(&mut self, argument: bool) -> Result<Node, String> {
+		let token = match self.next_token() {
+			Some(token) => token,
+			None => return Err("expected an argument but the formula ended".to_string()),
+		};
+
+		match token {
+			Token::Open => {
+				let (nodes, stop) = self.parse_row()?;
+				if stop != Stop::Close {
+					return Err("unclosed '{'".to_string());
+				}
+				Ok(Node::new(mrow(nodes)))
+			}
+
+			Token::Char(character) if character.is_ascii_digit() || character == '.' => {
+				let mut number = character.to_string();
+				if !argument {
+					let remaining = &self.source[self.index..];
+					let digits: String = remaining
+						.chars()
+						.take_while(|character| character.is_ascii_digit() || *character == '.')
+						.collect();
+					self.index += digits.len();
+					number.push_str(&digits);
+				}
+
+				//A trailing period ends the sentence rather than the number
+				if number.ends_with('.') && number.len() > 1 {
+					number.pop();
+					self.index -= 1;
+				}
+
+				match number.as_str() {
+					"." => Ok(Node::new(operator("."))),
+					number => Ok(Node::new(format!("<mn>{}</mn>", number))),
+				}
+			}
+
+			Token::Char(character) if character.is_alphabetic() => {
+				Ok(Node::new(format!("<mi>{}</mi>", character)))
+			}
+
+			Token::Char('-') => Ok(Node::new(operator("−"))),
+			Token::Char('~') => Ok(Node::new(String::from(
+				r#"<mspace width="0.25em"></mspace>"#,
+			))),
+			Token::Char(character) => Ok(Node::new(operator(&character.to_string()))),
+
+			Token::Command(name) => self.parse_command(&name),
+
+			Token::Close => Err("unexpected '}'".to_string()),
+			Token::Superscript | Token::Subscript => {
+				Err("double superscript or subscript".to_string())
+			}
+			Token::Ampersand => Err("'&' outside of an environment".to_string()),
+			Token::RowBreak => Err("'\\\\' outside of an environment".to_string()),
+		}
+	}
+
+	fn parse_delimiter(&mut self) -> Result<String, String> {
+		match self.next_token() {
+			Some(Token::Char(character)) => Ok(character.to_string()),
+			Some(Token::Command(name)) => match name.as_str() {
+				"{" | "}" => Ok(name),
+				"|" => Ok("‖".to_string()),
+				name => match lookup(OPERATORS, name) {
+					Some(symbol) => Ok(symbol.to_string()),
+					None => Err(format!("'\\{}' is not a delimiter", name)),
+				},
+			},
+			_ => Err("expected a delimiter after \\left or \\right".to_string()),
+		}
+	}
+
+	fn parse_command(&mut self, name: &str) -> Result<Node, String> {
+		if let Some(symbol) = lookup(GREEK, name) {
+			//Capital Greek letters are upright by convention
+			return Ok(Node::new(
+				match symbol.chars().next().map(char::is_uppercase) {
+					Some(true) => format!(r#"<mi mathvariant="normal">{}</mi>"#, symbol),
+					_ => format!("<mi>{}</mi>", symbol),
+				},
+			));
+		}
+		if let Some(symbol) = lookup(IDENTIFIERS, name) {
+			return Ok(Node::new(format!("<mi>{}</mi>", symbol)));
+		}
+		if let Some(symbol) = lookup(OPERATORS, name) {
+			return Ok(Node::new(operator(symbol)));
+		}
+		if let Some(symbol) = lookup(LARGE_OPERATORS, name) {
+			return Ok(Node {
+				mathml: format!(r#"<mo largeop="true">{}</mo>"#, symbol),
+				large_operator: !name.contains("int"),
+			});
+		}
+		if LIMIT_FUNCTIONS.contains(&name) {
+			return Ok(Node {
+				mathml: format!("<mo>{}</mo>", name),
+				large_operator: true,
+			});
+		}
+		if FUNCTIONS.contains(&name) {
+			return Ok(Node::new(format!("<mi>{}</mi><mo>&#x2061;</mo>", name)));
+		}
+		if let Some(width) = lookup(SPACES, name) {
+			return Ok(Node::new(format!(r#"<mspace width="{}"></mspace>"#, width)));
+		}
+		if let Some(accent) = lookup(ACCENTS, name) {
+			let base = self.parse_atom(true)?;
+			return Ok(Node::new(format!(
+				r#"<mover accent="true">{}<mo stretchy="true">{}</mo></mover>"#,
+				base.mathml, accent
+			)));
+		}
+		if let Some(accent) = lookup(UNDER_ACCENTS, name) {
+			let base = self.parse_atom(true)?;
+			return Ok(Node::new(format!(
+				r#"<munder accentunder="true">{}<mo stretchy="true">{}</mo></munder>"#,
+				base.mathml, accent
+			)));
+		}
+		if let Some(variant) = lookup(FONTS, name) {
+			let base = self.parse_atom(true)?;
+			return Ok(Node::new(format!(
+				r#"<mstyle mathvariant="{}">{}</mstyle>"#,
+				variant, base.mathml
+			)));
+		}
+
+		match name {
+			"frac" | "dfrac" | "tfrac" => {
+				let numerator = self.parse_atom(true)?;
+				let denominator = self.parse_atom(true)?;
+				Ok(Node::new(format!(
+					"<mfrac>{}{}</mfrac>",
+					numerator.mathml, denominator.mathml
+				)))
+			}
+
+			"binom" => {
+				let top = self.parse_atom(true)?;
+				let bottom = self.parse_atom(true)?;
+				Ok(Node::new(format!(
+					r#"<mrow><mo>(</mo><mfrac linethickness="0">{}{}</mfrac><mo>)</mo></mrow>"#,
+					top.mathml, bottom.mathml
+				)))
+			}
+
+			"sqrt" => {
+				self.skip_whitespace();
+				if self.source[self.index..].starts_with('[') {
+					let end = match self.source[self.index..].find(']') {
+						Some(end) => self.index + end,
+						None => return Err("unclosed '[' after \\sqrt".to_string()),
+					};
+					let degree = to_mathml_row(&self.source[self.index + 1..end], self.display)?;
+					self.index = end + 1;
+
+					let base = self.parse_atom(true)?;
+					return Ok(Node::new(format!(
+						"<mroot>{}{}</mroot>",
+						base.mathml, degree
+					)));
+				}
+
+				let base = self.parse_atom(true)?;
+				Ok(Node::new(format!("<msqrt>{}</msqrt>", base.mathml)))
+			}
+
+			"text" | "textrm" | "mbox" => {
+				let text = self.raw_group()?;
+				Ok(Node::new(format!("<mtext>{}</mtext>", escape_xml(&text))))
+			}
+
+			"operatorname" => {
+				let text = self.raw_group()?;
+				Ok(Node::new(format!(
+					"<mi>{}</mi><mo>&#x2061;</mo>",
+					escape_xml(text.trim())
+				)))
+			}
+
+			"left" => {
+				let open = self.parse_delimiter()?;
+				let (nodes, stop) = self.parse_row()?;
+				if stop != Stop::Right {
+					return Err("\\left without a matching \\right".to_string());
+				}
+				let close = self.parse_delimiter()?;
+
+				Ok(Node::new(format!(
+					"<mrow>{}{}{}</mrow>",
+					fence(&open),
+					mrow(nodes),
+					fence(&close)
+				)))
+			}
+
+			"begin" => {
+				let environment = self.environment_name()?;
+				self.parse_environment(&environment)
+			}
+
+			"overset" | "stackrel" | "underset" => {
+				let script = self.parse_atom(true)?;
+				let base = self.parse_atom(true)?;
+				let element = match name {
+					"underset" => "munder",
+					_ => "mover",
+				};
+				Ok(Node::new(format!(
+					"<{0}>{1}{2}</{0}>",
+					element, base.mathml, script.mathml
+				)))
+			}
+
+			"not" => {
+				let symbol = match self.next_token() {
+					Some(Token::Char(character)) => character.to_string(),
+					Some(Token::Command(name)) => match lookup(OPERATORS, &name) {
+						Some(symbol) => symbol.to_string(),
+						None => return Err(format!("cannot negate '\\{}'", name)),
+					},
+					_ => return Err("expected a relation after \\not".to_string()),
+				};
+				let negated = match lookup(NEGATIONS, &symbol) {
+					Some(negated) => negated.to_string(),
+					None => format!("{}\u{338}", symbol),
+				};
+				Ok(Node::new(operator(&negated)))
+			}
+
+			"bmod" => Ok(Node::new(String::from(
+				r#"<mo lspace="0.2222em" rspace="0.2222em">mod</mo>"#,
+			))),
+
+			"pmod" => {
+				let modulus = self.parse_atom(true)?;
+				Ok(Node::new(format!(
+					r#"<mrow><mspace width="1em"></mspace><mo>(</mo><mi>mod</mi><mspace width="0.3333em"></mspace>{}<mo>)</mo></mrow>"#,
+					modulus.mathml
+				)))
+			}
+
+			//Style switches only matter for the placement of limits here
+			"displaystyle" => {
+				self.display = true;
+				Ok(Node::new(String::new()))
+			}
+			"textstyle" | "scriptstyle" | "scriptscriptstyle" => {
+				self.display = false;
+				Ok(Node::new(String::new()))
+			}
+
+			"{" | "}" | "%" | "#" | "&" | "|" => Ok(Node::new(operator(match name {
+				"|" => "‖",
+				name => name,
+			}))),
+			"$" | "_" => Ok(Node::new(format!("<mi>{}</mi>", name))),
+
+			"right" => Err("\\right without a matching \\left".to_string()),
+			"end" => Err("\\end without a matching \\begin".to_string()),
+			name => {
+				let size = name.trim_end_matches(['l', 'r', 'm']);
+				match lookup(DELIMITER_SIZES, size) {
+					Some(size) => {
+						let delimiter = self.parse_delimiter()?;
+						Ok(Node::new(match delimiter.as_str() {
+							"." => String::new(),
+							delimiter => format!(
+								r#"<mo fence="true" stretchy="true" minsize="{0}" maxsize="{0}">{1}</mo>"#,
+								size,
+								escape_xml(delimiter)
+							),
+						}))
+					}
+					None => Err(format!("unsupported command '\\{}'", name)),
+				}
+			}
+		}
+	}
+
+	fn parse_environment(&mut self, environment: &str) -> Result<Node, String> {
+		let (open, close, align) = match environment {
+			"matrix" => ("", "", None),
+			"pmatrix" => ("(", ")", None),
+			"bmatrix" => ("[", "]", None),
+			"Bmatrix" => ("{", "}", None),
+			"vmatrix" => ("|", "|", None),
+			"Vmatrix" => ("‖", "‖", None),
+			"cases" => ("{", "", Some("left left")),
+			"aligned" | "align" | "align*" | "split" | "gathered" => ("", "", Some("right left")),
+			environment => return Err(format!("unsupported environment '{}'", environment)),
+		};
+
+		let mut rows = Vec::new();
+		let mut cells = Vec::new();
+		loop {
+			let (nodes, stop) = self.parse_row()?;
+			cells.push(row(nodes));
+
+			match stop {
+				Stop::Ampersand => {}
+				Stop::RowBreak => rows.push(std::mem::take(&mut cells)),
+				Stop::EndEnvironment => break,
+				_ => return Err(format!("missing \\end{{{}}}", environment)),
+			}
+		}
+		if cells.len() > 1 || cells.first().map(|cell| cell.as_str()) != Some("<mrow></mrow>") {
+			rows.push(cells);
+		}
+
+		let end = self.environment_name()?;
+		if end != environment {
+			return Err(format!(
+				"\\begin{{{}}} is closed by \\end{{{}}}",
+				environment, end
+			));
+		}
+
+		let mut table = match align {
+			Some(align) => format!(r#"<mtable columnalign="{}">"#, align),
+			None => String::from("<mtable>"),
+		};
+		for cells in rows {
+			table.push_str("<mtr>");
+			for cell in cells {
+				table.push_str("<mtd>");
+				table.push_str(&cell);
+				table.push_str("</mtd>");
+			}
+			table.push_str("</mtr>");
+		}
+		table.push_str("</mtable>");
+
+		Ok(Node::new(format!(
+			"<mrow>{}{}{}</mrow>",
+			fence(open),
+			table,
+			fence(close)
+		)))
+	}
+}
+
+fn to_mathml_row(source: &str, display: bool) -> Result<String, String> {
+	let mut parser = Parser {
+		source,
+		index: 0,
+		display,
+	};
+
+	let (nodes, stop) = parser.parse_row()?;
+	match stop {
+		Stop::End => Ok(mrow(nodes)),
+		Stop::Close => Err("unexpected '}'".to_string()),
+		Stop::Right => Err("\\right without a matching \\left".to_string()),
+		Stop::Ampersand => Err("'&' outside of an environment".to_string()),
+		Stop::RowBreak => Err("'\\\\' outside of an environment".to_string()),
+		Stop::EndEnvironment => Err("\\end without a matching \\begin".to_string()),
+	}
+}
+
+/*
+ * Returns a complete `<math>` element, keeping the LaTeX source as an
+ * annotation so it survives copy and paste.
+ */
+pub fn latex_to_mathml(source: &str, display: bool) -> Result<String, String> {
+	let contents = to_mathml_row(source.trim(), display)?;
+
+	Ok(format!(
+		concat!(
+			r#"<math xmlns="http://www.w3.org/1998/Math/MathML" display="{display}">"#,
+			"<semantics>{contents}",
+			r#"<annotation encoding="application/x-tex">{source}</annotation>"#,
+			"</semantics></math>"
+		),
+		display = if display { "block" } else { "inline" },
+		contents = contents,
+		source = escape_xml(source.trim()),
+	))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn inline(source: &str) -> String {
+		to_mathml_row(source, false).expect("formula should convert")
+	}
+
+	fn display(source: &str) -> String {
+		to_mathml_row(source, true).expect("formula should convert")
+	}
+
+	#[test]
+	fn identifiers_numbers_and_operators() {
+		assert_eq!(
+			inline("x + 12.5 - y"),
+			"<mrow><mi>x</mi><mo>+</mo><mn>12.5</mn><mo>−</mo><mi>y</mi></mrow>"
+		);
+		assert_eq!(
+			inline(r"\alpha \Gamma"),
+			r#"<mrow><mi>α</mi><mi mathvariant="normal">Γ</mi></mrow>"#
+		);
+	}
+
+	#[test]
+	fn scripts() {
+		assert_eq!(
+			inline("x^2"),
+			"<mrow><msup><mi>x</mi><mn>2</mn></msup></mrow>"
+		);
+		assert_eq!(
+			inline("x_i^{n+1}"),
+			"<mrow><msubsup><mi>x</mi><mi>i</mi><mrow><mi>n</mi><mo>+</mo><mn>1</mn></mrow></msubsup></mrow>"
+		);
+		//Only the first digit is the script without braces
+		assert_eq!(
+			inline("x^23"),
+			"<mrow><msup><mi>x</mi><mn>2</mn></msup><mn>3</mn></mrow>"
+		);
+		assert!(inline("f'").contains("<msup><mi>f</mi><mo>′</mo></msup>"));
+	}
+
+	#[test]
+	fn fractions_and_roots() {
+		assert_eq!(
+			inline(r"\frac12"),
+			"<mrow><mfrac><mn>1</mn><mn>2</mn></mfrac></mrow>"
+		);
+		assert_eq!(
+			inline(r"\sqrt[3]{x}"),
+			"<mrow><mroot><mrow><mi>x</mi></mrow><mrow><mn>3</mn></mrow></mroot></mrow>"
+		);
+	}
+
+	#[test]
+	fn limits_depend_on_display_style() {
+		assert!(display(r"\sum_{i=1}^n i").contains("<munderover><mo largeop=\"true\">∑</mo>"));
+		assert!(inline(r"\sum_{i=1}^n i").contains("<msubsup><mo largeop=\"true\">∑</mo>"));
+		assert!(inline(r"\displaystyle \sum_{i=1}^n i").contains("<munderover>"));
+		assert!(display(r"\textstyle \sum_{i=1}^n i").contains("<msubsup>"));
+	}
+
+	#[test]
+	fn delimiters() {
+		assert_eq!(
+			inline(r"\lvert x \rvert"),
+			"<mrow><mo>|</mo><mi>x</mi><mo>|</mo></mrow>"
+		);
+		assert_eq!(
+			inline(r"\left( x \right."),
+			r#"<mrow><mrow><mo fence="true" stretchy="true">(</mo><mrow><mi>x</mi></mrow></mrow></mrow>"#
+		);
+		assert!(inline(r"\big( x \bigr)").contains(r#"minsize="1.2em" maxsize="1.2em">(</mo>"#));
+		assert!(inline(r"\Biggl\{ x \Biggr\}").contains(r#"minsize="3em" maxsize="3em">{</mo>"#));
+	}
+
+	#[test]
+	fn negated_relations() {
+		assert_eq!(inline(r"\not\in"), "<mrow><mo>∉</mo></mrow>");
+		assert_eq!(inline(r"\not="), "<mrow><mo>≠</mo></mrow>");
+		assert_eq!(inline(r"\not\approx"), "<mrow><mo>≈\u{338}</mo></mrow>");
+		assert!(to_mathml_row(r"\not\frac12", false).is_err());
+	}
+
+	#[test]
+	fn modulo() {
+		assert!(inline(r"a \bmod b").contains(">mod</mo>"));
+		assert!(inline(r"a \equiv b \pmod{n}").contains("<mi>mod</mi>"));
+	}
+
+	#[test]
+	fn stacked_symbols() {
+		assert_eq!(
+			inline(r"\overset{!}{=}"),
+			"<mrow><mover><mrow><mo>=</mo></mrow><mrow><mo>!</mo></mrow></mover></mrow>"
+		);
+		assert!(inline(r"\underset{x}{\arg}").starts_with("<mrow><munder>"));
+		assert!(inline(r"\stackrel{def}{=}").starts_with("<mrow><mover>"));
+	}
+
+	#[test]
+	fn environments() {
+		assert_eq!(
+			inline(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"),
+			concat!(
+				r#"<mrow><mrow><mo fence="true" stretchy="true">(</mo><mtable>"#,
+				"<mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr>",
+				"<mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr>",
+				r#"</mtable><mo fence="true" stretchy="true">)</mo></mrow></mrow>"#
+			)
+		);
+		assert!(to_mathml_row(r"\begin{pmatrix} a \end{bmatrix}", false).is_err());
+		assert!(to_mathml_row(r"\begin{tabular} a \end{tabular}", false).is_err());
+	}
+
+	#[test]
+	fn errors() {
+		assert!(to_mathml_row(r"\unknowncommand", false).is_err());
+		assert!(to_mathml_row("{x", false).is_err());
+		assert!(to_mathml_row("x}", false).is_err());
+		assert!(to_mathml_row(r"\left( x", false).is_err());
+		assert!(to_mathml_row("a & b", false).is_err());
+		assert!(to_mathml_row("x^", false).is_err());
+	}
+
+	#[test]
+	fn math_element_keeps_escaped_source() {
+		let mathml = latex_to_mathml(" a < b ", true).unwrap();
+		assert!(mathml
+			.starts_with(r#"<math xmlns="http://www.w3.org/1998/Math/MathML" display="block">"#));
+		assert!(
+			mathml.contains(r#"<annotation encoding="application/x-tex">a &lt; b</annotation>"#)
+		);
+	}
+}
@@ -1,43 +1,577 @@
 use std::collections::HashMap;
 
-pub fn format_template(template: String, values: HashMap<&str, String>) -> String {
-	let mut output = template;
-
-	let mut index = 0;
-	while index < output.len() {
-		if output.as_bytes()[index] == b'$' {
-			//Start of a substitution
-			let start = index;
-			index += 1;
-
-			let mut end = None;
-			while index < output.len() {
-				if output.as_bytes()[index] == b'$' {
-					//End of subsitution
-					end = Some(index);
+/*
+ * Template syntax:
+ *
+ *   $KEY$                          Substitute a value, errors if it is missing
+ *   $KEY|fallback$                 Substitute a value or the fallback if it is missing or empty
+ *   $KEY:escape$                   Substitute a value with HTML special characters escaped
+ *   $entry.KEY$                    Look up a key inside a loop binding or map
+ *   {% if KEY %} {% else %} {% endif %}
+ *   {% if not KEY %} ... {% endif %}
+ *   {% for entry in KEY %} ... {% endfor %}
+ *   {% include "file.html" %}      Render another fragment file in the current scope
+ *
+ * A value is "truthy" if it is non-empty text, `true`, or a non-empty list/map.
+ * Keys missing in an `if` are treated as falsy so optional values can be tested.
+ */
+
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+	Text(String),
+	Bool(bool),
+	List(Vec<Value>),
+	Map(Values),
+}
+
+pub type Values = HashMap<String, Value>;
+
+impl Value {
+	fn is_truthy(&self) -> bool {
+		match self {
+			Value::Text(text) => !text.is_empty(),
+			Value::Bool(value) => *value,
+			Value::List(list) => !list.is_empty(),
+			Value::Map(map) => !map.is_empty(),
+		}
+	}
+}
+
+impl From<String> for Value {
+	fn from(text: String) -> Value {
+		Value::Text(text)
+	}
+}
+
+impl From<&str> for Value {
+	fn from(text: &str) -> Value {
+		Value::Text(text.to_string())
+	}
+}
+
+impl From<bool> for Value {
+	fn from(value: bool) -> Value {
+		Value::Bool(value)
+	}
+}
+
+impl From<Vec<Value>> for Value {
+	fn from(list: Vec<Value>) -> Value {
+		Value::List(list)
+	}
+}
+
+impl From<Vec<Values>> for Value {
+	fn from(list: Vec<Values>) -> Value {
+		Value::List(list.into_iter().map(Value::Map).collect())
+	}
+}
+
+impl From<Values> for Value {
+	fn from(map: Values) -> Value {
+		Value::Map(map)
+	}
+}
+
+//Tests panic instead so a malformed template does not take down the test runner
+#[cfg(not(test))]
+macro_rules! template_error {
+	( $($arg:tt)* ) => {{
+		eprintln!("Error in template: {}", format_args!($($arg)*));
+		std::process::exit(-1);
+	}};
+}
+
+#[cfg(test)]
+macro_rules! template_error {
+	( $($arg:tt)* ) => {{
+		panic!("Error in template: {}", format_args!($($arg)*));
+	}};
+}
+
+#[derive(Debug)]
+enum Node<'a> {
+	Text(&'a str),
+
+	Substitution {
+		path: &'a str,
+		escape: bool,
+		fallback: Option<&'a str>,
+	},
+
+	If {
+		negated: bool,
+		path: &'a str,
+		then: Vec<Node<'a>>,
+		otherwise: Vec<Node<'a>>,
+	},
+
+	For {
+		binding: &'a str,
+		path: &'a str,
+		body: Vec<Node<'a>>,
+	},
+
+	Include(&'a str),
+}
+
+struct Parser<'a> {
+	source: &'a str,
+	index: usize,
+}
+
+impl<'a> Parser<'a> {
+	/*
+	 * Parses nodes until the end of the source or until hitting a block tag
+	 * which the caller handles (`else`, `endif`, `endfor`). Returns the nodes
+	 * along with the terminating tag, if any.
+	 */
+	fn parse_nodes(&mut self) -> (Vec<Node<'a>>, Option<&'a str>) {
+		let mut nodes = Vec::new();
+
+		while self.index < self.source.len() {
+			let remaining = &self.source[self.index..];
+
+			let next_substitution = remaining.find('$');
+			let next_tag = remaining.find("{%");
+			let next = match (next_substitution, next_tag) {
+				(Some(substitution), Some(tag)) => substitution.min(tag),
+				(Some(substitution), None) => substitution,
+				(None, Some(tag)) => tag,
+				(None, None) => {
+					nodes.push(Node::Text(remaining));
+					self.index = self.source.len();
 					break;
 				}
-				index += 1;
+			};
+
+			if next > 0 {
+				nodes.push(Node::Text(&remaining[..next]));
+				self.index += next;
 			}
 
-			if let Some(end) = end {
-				let key = &output[start + 1..end];
-				let value = match values.get(key) {
-					Some(value) => value,
-					None => {
-						eprintln!("Error failed to template substitute for key '{}'", key);
-						std::process::exit(-1);
+			let remaining = &self.source[self.index..];
+			if let Some(after) = remaining.strip_prefix('$') {
+				match after.find('$') {
+					Some(end) => {
+						let contents = &after[..end];
+						let (path, fallback) = match contents.find('|') {
+							Some(bar) => (&contents[..bar], Some(&contents[bar + 1..])),
+							None => (contents, None),
+						};
+						let (path, escape) = match path.split_once(':') {
+							Some((path, "escape")) => (path, true),
+							Some((_, filter)) => {
+								template_error!("Unknown filter '{}' in '${}$'", filter, contents)
+							}
+							None => (path, false),
+						};
+
+						nodes.push(Node::Substitution {
+							path: path.trim(),
+							escape,
+							fallback,
+						});
+						self.index += end + 2;
 					}
-				};
 
-				output.replace_range(start..=end, value);
-				index = start + value.len();
+					None => {
+						//A lone dollar sign, not a substitution
+						nodes.push(Node::Text(&remaining[..1]));
+						self.index += 1;
+					}
+				}
 				continue;
 			}
+
+			let end = match remaining.find("%}") {
+				Some(end) => end,
+				None => template_error!(
+					"Unterminated tag '{}'",
+					remaining.lines().next().unwrap_or("")
+				),
+			};
+			let tag = remaining[2..end].trim();
+			self.index += end + 2;
+
+			let mut words = tag.split_whitespace();
+			match words.next() {
+				Some("if") => {
+					let (negated, path) = match (words.next(), words.next()) {
+						(Some("not"), Some(path)) => (true, path),
+						(Some(path), None) => (false, path),
+						_ => template_error!("Malformed tag '{{% {} %}}'", tag),
+					};
+
+					let (then, terminator) = self.parse_nodes();
+					let otherwise = match terminator {
+						Some("endif") => Vec::new(),
+
+						Some("else") => {
+							let (otherwise, terminator) = self.parse_nodes();
+							if terminator != Some("endif") {
+								template_error!("Expected '{{% endif %}}' after '{{% else %}}'");
+							}
+							otherwise
+						}
+
+						_ => template_error!("Missing '{{% endif %}}' for '{{% {} %}}'", tag),
+					};
+
+					nodes.push(Node::If {
+						negated,
+						path,
+						then,
+						otherwise,
+					});
+				}
+
+				Some("for") => {
+					let (binding, path) = match (words.next(), words.next(), words.next()) {
+						(Some(binding), Some("in"), Some(path)) => (binding, path),
+						_ => template_error!("Malformed tag '{{% {} %}}'", tag),
+					};
+
+					let (body, terminator) = self.parse_nodes();
+					if terminator != Some("endfor") {
+						template_error!("Missing '{{% endfor %}}' for '{{% {} %}}'", tag);
+					}
+
+					nodes.push(Node::For {
+						binding,
+						path,
+						body,
+					});
+				}
+
+				Some("include") => {
+					let name = tag["include".len()..].trim();
+					let name = name.trim_matches('"');
+					if name.is_empty() {
+						template_error!("Malformed tag '{{% {} %}}'", tag);
+					}
+					nodes.push(Node::Include(name));
+				}
+
+				Some(terminator @ "else")
+				| Some(terminator @ "endif")
+				| Some(terminator @ "endfor") => {
+					return (nodes, Some(terminator));
+				}
+
+				_ => template_error!("Unknown tag '{{% {} %}}'", tag),
+			}
 		}
 
-		index += 1;
+		(nodes, None)
+	}
+}
+
+fn parse(source: &str) -> Vec<Node<'_>> {
+	let mut parser = Parser { source, index: 0 };
+
+	let (nodes, terminator) = parser.parse_nodes();
+	if let Some(terminator) = terminator {
+		template_error!("Unexpected '{{% {} %}}'", terminator);
 	}
 
+	nodes
+}
+
+struct Scope<'a> {
+	values: &'a Values,
+	bindings: Vec<(&'a str, &'a Value)>,
+	includes: &'a dyn Fn(&str) -> String,
+	depth: usize,
+}
+
+impl<'a> Scope<'a> {
+	fn lookup(&self, path: &str) -> Option<&'a Value> {
+		let mut segments = path.split('.');
+		let first = segments.next()?;
+
+		let mut value = match self.bindings.iter().rev().find(|(name, _)| *name == first) {
+			Some((_, value)) => *value,
+			None => self.values.get(first)?,
+		};
+
+		for segment in segments {
+			value = match value {
+				Value::Map(map) => map.get(segment)?,
+				_ => return None,
+			};
+		}
+
+		Some(value)
+	}
+}
+
+fn render<'a>(nodes: &'a [Node<'a>], scope: &mut Scope<'a>, output: &mut String) {
+	for node in nodes {
+		match node {
+			Node::Text(text) => output.push_str(text),
+
+			Node::Substitution {
+				path,
+				escape,
+				fallback,
+			} => match (scope.lookup(path), fallback) {
+				(Some(Value::Text(text)), Some(fallback)) if text.is_empty() => {
+					output.push_str(fallback)
+				}
+				(Some(Value::Text(text)), _) if *escape => {
+					output.push_str(&crate::escape_xml(text))
+				}
+				(Some(Value::Text(text)), _) => output.push_str(text),
+				(Some(Value::Bool(value)), _) => {
+					output.push_str(if *value { "true" } else { "false" })
+				}
+				(Some(_), _) => {
+					template_error!("Key '{}' is a list or map and cannot be substituted", path)
+				}
+				(None, Some(fallback)) => output.push_str(fallback),
+				(None, None) => {
+					eprintln!("Error failed to template substitute for key '{}'", path);
+					std::process::exit(-1);
+				}
+			},
+
+			Node::If {
+				negated,
+				path,
+				then,
+				otherwise,
+			} => {
+				let truthy = scope.lookup(path).map(Value::is_truthy).unwrap_or(false);
+				if truthy != *negated {
+					render(then, scope, output);
+				} else {
+					render(otherwise, scope, output);
+				}
+			}
+
+			Node::For {
+				binding,
+				path,
+				body,
+			} => {
+				let list = match scope.lookup(path) {
+					Some(Value::List(list)) => list,
+					Some(_) => {
+						template_error!("Key '{}' is not a list and cannot be looped over", path)
+					}
+					None => template_error!("Failed to find list '{}' to loop over", path),
+				};
+
+				for item in list {
+					scope.bindings.push((binding, item));
+					render(body, scope, output);
+					scope.bindings.pop();
+				}
+			}
+
+			Node::Include(name) => {
+				if scope.depth >= MAX_INCLUDE_DEPTH {
+					template_error!("Includes nested too deeply while including '{}'", name);
+				}
+
+				let source = (scope.includes)(name);
+				let nodes = parse(&source);
+
+				let mut inner = Scope {
+					values: scope.values,
+					bindings: scope.bindings.clone(),
+					includes: scope.includes,
+					depth: scope.depth + 1,
+				};
+				render(&nodes, &mut inner, output);
+			}
+		}
+	}
+}
+
+pub fn format_template(
+	template: &str,
+	values: &Values,
+	includes: &dyn Fn(&str) -> String,
+) -> String {
+	let nodes = parse(template);
+
+	let mut scope = Scope {
+		values,
+		bindings: Vec::new(),
+		includes,
+		depth: 0,
+	};
+
+	let mut output = String::new();
+	render(&nodes, &mut scope, &mut output);
 	output
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn values(pairs: Vec<(&str, Value)>) -> Values {
+		pairs
+			.into_iter()
+			.map(|(key, value)| (key.to_string(), value))
+			.collect()
+	}
+
+	fn no_includes(name: &str) -> String {
+		panic!("unexpected include of '{}'", name)
+	}
+
+	fn render(template: &str, values: &Values) -> String {
+		format_template(template, values, &no_includes)
+	}
+
+	#[test]
+	fn substitution() {
+		let values = values(vec![
+			("TITLE", "Hello".into()),
+			("DRAFT", true.into()),
+			("post", values(vec![("NAME", "first".into())]).into()),
+		]);
+		assert_eq!(render("<h1>$TITLE$</h1>", &values), "<h1>Hello</h1>");
+		assert_eq!(render("$ TITLE $", &values), "Hello");
+		assert_eq!(render("$DRAFT$", &values), "true");
+		assert_eq!(render("$post.NAME$", &values), "first");
+	}
+
+	#[test]
+	fn fallback() {
+		let values = values(vec![("EMPTY", "".into()), ("SET", "value".into())]);
+		assert_eq!(render("$MISSING|none$", &values), "none");
+		assert_eq!(render("$EMPTY|none$", &values), "none");
+		assert_eq!(render("$SET|none$", &values), "value");
+		assert_eq!(render("$MISSING|$", &values), "");
+	}
+
+	#[test]
+	fn lone_dollar_sign() {
+		let values = Values::new();
+		assert_eq!(render("Costs 5$", &values), "Costs 5$");
+		assert_eq!(render("$", &values), "$");
+	}
+
+	#[test]
+	#[should_panic(expected = "cannot be substituted")]
+	fn list_cannot_be_substituted() {
+		let values = values(vec![("LIST", Value::List(Vec::new()))]);
+		render("$LIST$", &values);
+	}
+
+	#[test]
+	fn conditionals() {
+		let values = values(vec![
+			("YES", true.into()),
+			("NO", false.into()),
+			("TEXT", "x".into()),
+			("EMPTY", "".into()),
+			("LIST", Value::List(Vec::new())),
+		]);
+		assert_eq!(render("{% if YES %}a{% endif %}", &values), "a");
+		assert_eq!(render("{% if NO %}a{% else %}b{% endif %}", &values), "b");
+		assert_eq!(render("{% if not NO %}a{% endif %}", &values), "a");
+		assert_eq!(render("{% if TEXT %}a{% endif %}", &values), "a");
+		assert_eq!(
+			render("{% if EMPTY %}a{% else %}b{% endif %}", &values),
+			"b"
+		);
+		assert_eq!(render("{% if LIST %}a{% else %}b{% endif %}", &values), "b");
+		assert_eq!(
+			render("{% if MISSING %}a{% else %}b{% endif %}", &values),
+			"b"
+		);
+	}
+
+	#[test]
+	fn nested_if_and_for() {
+		let tags: Vec<Values> = vec![
+			values(vec![("NAME", "rust".into()), ("CURRENT", true.into())]),
+			values(vec![("NAME", "web".into()), ("CURRENT", false.into())]),
+		];
+		let posts: Vec<Values> = vec![
+			values(vec![("TITLE", "One".into()), ("TAGS", tags.into())]),
+			values(vec![
+				("TITLE", "Two".into()),
+				("TAGS", Value::List(Vec::new())),
+			]),
+		];
+		let values = values(vec![("POSTS", posts.into()), ("SITE", "Blog".into())]);
+
+		let template = concat!(
+			"{% for post in POSTS %}$post.TITLE$@$SITE$:",
+			"{% if post.TAGS %}{% for tag in post.TAGS %}",
+			"{% if tag.CURRENT %}*{% endif %}$tag.NAME$ ",
+			"{% endfor %}{% else %}none{% endif %};",
+			"{% endfor %}"
+		);
+		assert_eq!(
+			render(template, &values),
+			"One@Blog:*rust web ;Two@Blog:none;"
+		);
+	}
+
+	#[test]
+	#[should_panic(expected = "Missing '{% endif %}'")]
+	fn unterminated_if() {
+		render("{% if YES %}a", &Values::new());
+	}
+
+	#[test]
+	fn include() {
+		let includes = |name: &str| match name {
+			"outer.html" => String::from("[{% include \"inner.html\" %}]"),
+			"inner.html" => String::from("$TITLE$"),
+			name => panic!("unexpected include of '{}'", name),
+		};
+		let title = values(vec![("TITLE", "Hello".into())]);
+		assert_eq!(
+			format_template("{% include \"outer.html\" %}", &title, &includes),
+			"[Hello]"
+		);
+
+		//Loop bindings are visible inside the included fragment
+		let entries: Vec<Values> = vec![title];
+		let values = values(vec![("ENTRIES", entries.into())]);
+		let includes = |_: &str| String::from("$entry.TITLE$");
+		assert_eq!(
+			format_template(
+				"{% for entry in ENTRIES %}{% include \"entry.html\" %}{% endfor %}",
+				&values,
+				&includes
+			),
+			"Hello"
+		);
+	}
+
+	#[test]
+	#[should_panic(expected = "Includes nested too deeply")]
+	fn include_recursion_is_limited() {
+		let includes = |_: &str| String::from("{% include \"self.html\" %}");
+		format_template("{% include \"self.html\" %}", &Values::new(), &includes);
+	}
+
+	#[test]
+	fn escape_filter() {
+		let values = values(vec![("TITLE", "Say \"hi\" & <b>'bye'</b>".into())]);
+		assert_eq!(
+			render("$TITLE:escape$", &values),
+			"Say &quot;hi&quot; &amp; &lt;b&gt;&apos;bye&apos;&lt;/b&gt;"
+		);
+		assert_eq!(render("$TITLE$", &values), "Say \"hi\" & <b>'bye'</b>");
+		assert_eq!(render("$MISSING:escape|<none>$", &values), "<none>");
+	}
+
+	#[test]
+	#[should_panic(expected = "Unknown filter")]
+	fn unknown_filter() {
+		render("$TITLE:upper$", &Values::new());
+	}
+}